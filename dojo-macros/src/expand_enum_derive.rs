@@ -108,6 +108,10 @@ pub fn expand_enum_derive(
                 }
             }
         }
+
+        impl dojo_orm::SqlType for #ident {
+            const SQL_TYPE: &'static str = #name;
+        }
     };
 
     // Return the generated impl
@@ -1,9 +1,42 @@
-use syn::{Data, Fields};
+use syn::{Data, DeriveInput, Fields};
 
 #[derive(deluxe::ExtractAttributes)]
 #[deluxe(attributes(dojo))]
 struct ModelStructAttrs {
     name: String,
+    #[deluxe(default)]
+    schema: Option<String>,
+}
+
+#[derive(deluxe::ExtractAttributes)]
+#[deluxe(attributes(dojo))]
+struct ModelFieldAttrs {
+    #[deluxe(default = false)]
+    primary_key: bool,
+    #[deluxe(default = false)]
+    cursor: bool,
+}
+
+/// The `#[dojo(primary_key)]` and `#[dojo(cursor)]` fields, in declaration order.
+fn extract_field_attrs(
+    ast: &mut DeriveInput,
+) -> deluxe::Result<(Vec<syn::Field>, Vec<syn::Field>)> {
+    let mut primary_key = vec![];
+    let mut cursor = vec![];
+
+    if let Data::Struct(s) = &mut ast.data {
+        for field in s.fields.iter_mut() {
+            let attrs: ModelFieldAttrs = deluxe::extract_attributes(field)?;
+            if attrs.primary_key {
+                primary_key.push(field.clone());
+            }
+            if attrs.cursor {
+                cursor.push(field.clone());
+            }
+        }
+    }
+
+    Ok((primary_key, cursor))
 }
 
 pub fn expand_model_derive(
@@ -13,7 +46,8 @@ pub fn expand_model_derive(
     let mut ast = syn::parse2::<syn::DeriveInput>(input)?;
 
     // Extract the attributes from the input
-    let ModelStructAttrs { name } = deluxe::extract_attributes(&mut ast)?;
+    let ModelStructAttrs { name, schema } = deluxe::extract_attributes(&mut ast)?;
+    let (primary_key, cursor) = extract_field_attrs(&mut ast)?;
 
     // Define impl variables
     let ident = &ast.ident;
@@ -36,12 +70,128 @@ pub fn expand_model_derive(
         .map(|i| i.to_string())
         .collect::<Vec<_>>();
 
+    let field_tys = fields.iter().map(|f| f.ty.clone()).collect::<Vec<_>>();
+
+    // the typed column handles live on a companion struct rather than as consts on the
+    // model, where a field like `name` would shadow `Model::NAME`
+    let vis = &ast.vis;
+    let columns_ident = quote::format_ident!("{}Columns", ident);
+    let columns_doc = format!(
+        "The typed columns of [`{}`], from `{}::columns()`.",
+        ident, ident
+    );
+
+    let schema = schema.map(|schema| {
+        quote::quote! {
+            const SCHEMA: Option<&'static str> = Some(#schema);
+        }
+    });
+
+    let primary_key = (!primary_key.is_empty()).then(|| {
+        let key_idents = primary_key
+            .iter()
+            .map(|f| f.ident.clone().unwrap())
+            .collect::<Vec<_>>();
+        let key_tys = primary_key.iter().map(|f| f.ty.clone()).collect::<Vec<_>>();
+
+        // a single key is used as is, a composite key becomes a tuple of its fields
+        let (key_ty, key_params, key_value, keys_in) = if key_idents.len() == 1 {
+            let key_ty = &key_tys[0];
+            let key_ident = &key_idents[0];
+            (
+                quote::quote! { #key_ty },
+                quote::quote! { vec![key] },
+                quote::quote! { self.#key_ident.clone() },
+                quote::quote! {
+                    dojo_orm::ops::in_array(stringify!(#key_ident), ids.to_vec())
+                },
+            )
+        } else {
+            let indexes = (0..key_idents.len()).map(syn::Index::from);
+            (
+                quote::quote! { (#(#key_tys),*) },
+                quote::quote! { vec![#(&key.#indexes),*] },
+                quote::quote! { (#(self.#key_idents.clone()),*) },
+                quote::quote! { dojo_orm::ops::in_tuples(Self::PRIMARY_KEY, ids) },
+            )
+        };
+
+        quote::quote! {
+            impl #impl_generics dojo_orm::PrimaryKey for #ident #ty_generics #where_clause {
+                const PRIMARY_KEY: &'static [&'static str] = &[
+                    #(stringify!(#key_idents)),*
+                ];
+
+                type Key = #key_ty;
+
+                fn key_params(key: &Self::Key) -> Vec<&(dyn dojo_orm::types::ToSql + Sync)> {
+                    #key_params
+                }
+
+                fn primary_key_params(&self) -> Vec<&(dyn dojo_orm::types::ToSql + Sync)> {
+                    vec![#(&self.#key_idents),*]
+                }
+
+                fn primary_key(&self) -> Self::Key {
+                    #key_value
+                }
+
+                fn keys_in(ids: &[Self::Key]) -> dojo_orm::ops::Op<'static> {
+                    #keys_in
+                }
+            }
+        }
+    });
+
+    // the cursor holds the marked fields by name, in declaration order
+    let cursor = (!cursor.is_empty()).then(|| {
+        let cursor_idents = cursor
+            .iter()
+            .map(|f| f.ident.clone().unwrap())
+            .collect::<Vec<_>>();
+
+        quote::quote! {
+            impl #impl_generics dojo_orm::pagination::CursorExt<dojo_orm::pagination::Cursor>
+                for #ident #ty_generics #where_clause
+            {
+                fn cursor(&self) -> dojo_orm::pagination::Cursor {
+                    dojo_orm::pagination::Cursor {
+                        values: vec![#((
+                            stringify!(#cursor_idents).to_string(),
+                            self.#cursor_idents.clone().into(),
+                        )),*],
+                    }
+                }
+            }
+        }
+    });
+
     // Define the output tokens
     let expanded = quote::quote! {
+        #[doc = #columns_doc]
+        #[allow(dead_code)]
+        #[derive(Clone, Copy, Debug)]
+        #vis struct #columns_ident #impl_generics #where_clause {
+            #(
+                pub #field_idents: dojo_orm::Column<#ident #ty_generics, #field_tys>,
+            )*
+        }
+
+        #[allow(dead_code)]
+        impl #impl_generics #ident #ty_generics #where_clause {
+            pub const fn columns() -> #columns_ident #ty_generics {
+                #columns_ident {
+                    #(#field_idents: dojo_orm::Column::new(#field_idents_str),)*
+                }
+            }
+        }
+
         #[async_trait::async_trait]
         impl #impl_generics dojo_orm::Model for #ident #ty_generics #where_clause {
             const NAME: &'static str = #name;
 
+            #schema
+
             const COLUMNS: &'static [&'static str] = &[
                 #(#field_idents_str),*
             ];
@@ -50,12 +200,21 @@ pub fn expand_model_derive(
                 vec![#(&self.#field_idents),*]
             }
 
-            fn from_row(row: tokio_postgres::Row) -> anyhow::Result<Self> {
+            fn from_row(row: tokio_postgres::Row) -> dojo_orm::Result<Self> {
                 Ok(#ident {
-                    #(#field_idents: row.try_get(stringify!(#field_idents))?),*
+                    #(#field_idents: row
+                        .try_get(stringify!(#field_idents))
+                        .map_err(|e| dojo_orm::Error::Decode {
+                            column: stringify!(#field_idents).to_string(),
+                            source: e,
+                        })?),*
                 })
             }
         }
+
+        #primary_key
+
+        #cursor
     };
 
     // Return the generated impl
@@ -0,0 +1,118 @@
+use syn::{Data, DeriveInput, Fields};
+
+#[derive(deluxe::ExtractAttributes)]
+#[deluxe(attributes(dojo))]
+struct ProjectionStructAttrs {
+    #[deluxe(default)]
+    model: Option<syn::Path>,
+}
+
+#[derive(deluxe::ExtractAttributes)]
+#[deluxe(attributes(dojo))]
+struct ProjectionFieldAttrs {
+    #[deluxe(default)]
+    expr: Option<String>,
+}
+
+/// The `#[dojo(expr = "...")]` of each field, in declaration order.
+fn extract_field_exprs(ast: &mut DeriveInput) -> deluxe::Result<Vec<Option<String>>> {
+    let mut exprs = vec![];
+
+    if let Data::Struct(s) = &mut ast.data {
+        for field in s.fields.iter_mut() {
+            let attrs: ProjectionFieldAttrs = deluxe::extract_attributes(field)?;
+            exprs.push(attrs.expr);
+        }
+    }
+
+    Ok(exprs)
+}
+
+pub fn expand_projection_derive(
+    input: proc_macro2::TokenStream,
+) -> deluxe::Result<proc_macro2::TokenStream> {
+    // Parse the input tokens into a syntax tree
+    let mut ast = syn::parse2::<syn::DeriveInput>(input)?;
+
+    let ProjectionStructAttrs { model } = deluxe::extract_attributes(&mut ast)?;
+    let exprs = extract_field_exprs(&mut ast)?;
+
+    // Define impl variables
+    let ident = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let fields: Fields = match ast.data.clone() {
+        Data::Struct(data) => data.fields,
+        _ => panic!("Projection can only be derived for structs"),
+    };
+
+    // Get the field idents
+    let field_idents = fields
+        .iter()
+        .filter_map(|f| f.ident.clone())
+        .collect::<Vec<_>>();
+
+    let field_idents_str = field_idents
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>();
+
+    // plain fields read the model column of the same name, the others their expression
+    let select = field_idents_str
+        .iter()
+        .zip(&exprs)
+        .map(|(name, expr)| match expr {
+            Some(expr) => quote::quote! { (#name, Some(#expr)) },
+            None => quote::quote! { (#name, None) },
+        })
+        .collect::<Vec<_>>();
+
+    // with the model known, a plain field that isn't one of its columns, or has another
+    // type, fails to compile rather than when the query is built
+    let checks = model.map(|model| {
+        let plain = fields
+            .iter()
+            .zip(&exprs)
+            .filter(|(_, expr)| expr.is_none())
+            .map(|(f, _)| (f.ident.clone().unwrap(), f.ty.clone()))
+            .collect::<Vec<_>>();
+        let plain_idents = plain.iter().map(|(ident, _)| ident);
+        let plain_tys = plain.iter().map(|(_, ty)| ty);
+
+        quote::quote! {
+            const _: () = {
+                #[allow(dead_code)]
+                fn check_columns() {
+                    #(
+                        let _: dojo_orm::Column<#model, #plain_tys> = #model::columns().#plain_idents;
+                    )*
+                }
+            };
+        }
+    });
+
+    // Define the output tokens
+    let expanded = quote::quote! {
+        impl #impl_generics dojo_orm::Projection for #ident #ty_generics #where_clause {
+            const SELECT: &'static [(&'static str, Option<&'static str>)] = &[
+                #(#select),*
+            ];
+
+            fn from_row(row: tokio_postgres::Row) -> dojo_orm::Result<Self> {
+                Ok(#ident {
+                    #(#field_idents: row
+                        .try_get(stringify!(#field_idents))
+                        .map_err(|e| dojo_orm::Error::Decode {
+                            column: stringify!(#field_idents).to_string(),
+                            source: e,
+                        })?),*
+                })
+            }
+        }
+
+        #checks
+    };
+
+    // Return the generated impl
+    Ok(expanded)
+}
@@ -3,12 +3,14 @@ use proc_macro::TokenStream;
 use crate::expand_embedded_derive::expand_embedded_derive;
 use crate::expand_enum_derive::expand_enum_derive;
 use crate::expand_model_derive::expand_model_derive;
+use crate::expand_projection_derive::expand_projection_derive;
 use crate::expand_update_model_derive::expand_update_model_derive;
 
 mod common;
 mod expand_embedded_derive;
 mod expand_enum_derive;
 mod expand_model_derive;
+mod expand_projection_derive;
 mod expand_update_model_derive;
 
 #[proc_macro_derive(Model, attributes(dojo))]
@@ -30,3 +32,8 @@ pub fn embedded_derive_macro(input: TokenStream) -> TokenStream {
 pub fn update_model_derive_macro(input: TokenStream) -> TokenStream {
     expand_update_model_derive(input.into()).unwrap().into()
 }
+
+#[proc_macro_derive(Projection, attributes(dojo))]
+pub fn projection_derive_macro(input: TokenStream) -> TokenStream {
+    expand_projection_derive(input.into()).unwrap().into()
+}
@@ -0,0 +1,141 @@
+use std::future::Future;
+
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{columns, table};
+use crate::model::PrimaryKey;
+use crate::ops::{row_gt, where_sql, Op};
+use crate::order_by::{order_by_sql, Order, OrderBy};
+use crate::transaction::{Atomic, BoxFuture};
+use crate::types::ToSql;
+
+/// Walks the matching rows in primary key order, `size` at a time, paging by keyset.
+pub struct BatchClause<'a, T, E>
+where
+    T: PrimaryKey,
+    E: Executor,
+{
+    pub(crate) executor: &'a E,
+    pub(crate) params: &'a [&'a (dyn ToSql + Sync)],
+    pub(crate) ops: &'a [Op<'a>],
+    pub(crate) size: i64,
+    pub(crate) after: Option<T::Key>,
+}
+
+impl<'a, T, E> BatchClause<'a, T, E>
+where
+    T: PrimaryKey,
+    E: Executor,
+{
+    /// Resumes after `key`, e.g. the last key of the batch a previous run finished.
+    pub fn after(&mut self, key: T::Key) -> &mut Self {
+        self.after = Some(key);
+        self
+    }
+
+    fn build<'b>(
+        &'b self,
+        after: Option<&'b T::Key>,
+    ) -> Result<(String, Vec<&'b (dyn ToSql + Sync)>)> {
+        let mut params_index = 1;
+
+        let mut params = self.params.to_vec();
+        let mut query = "SELECT ".to_string();
+        query.push_str(&columns::<T>());
+        query.push_str(" FROM ");
+        query.push_str(&table::<T>());
+
+        let filters = where_sql::<T>(self.ops, &mut params_index, &mut params)?;
+        query.push_str(&filters);
+        if let Some(after) = after {
+            let key = T::key_params(after);
+            let (q, _) = row_gt(T::PRIMARY_KEY, &key).sql(&mut params_index);
+            query.push_str(if filters.is_empty() {
+                " WHERE "
+            } else {
+                " AND "
+            });
+            query.push_str(&q);
+            params.extend(key);
+        }
+
+        let orders = T::PRIMARY_KEY
+            .iter()
+            .map(|column| OrderBy::new(column, Order::Asc))
+            .collect::<Vec<_>>();
+        query.push_str(&order_by_sql::<T>(&orders)?);
+        query.push_str(format!(" LIMIT {}", self.size).as_str());
+
+        Ok((query, params))
+    }
+
+    async fn fetch<X: Executor>(&self, executor: &X, after: Option<&T::Key>) -> Result<Vec<T>> {
+        let (query, params) = self.build(after)?;
+        debug!("query: {}, params: {:?}", query, params);
+        let mut rows = vec![];
+        for row in executor.query(query.as_str(), &params).await? {
+            rows.push(T::from_row(row)?);
+        }
+
+        Ok(rows)
+    }
+
+    /// Calls `f` with each batch in turn, stopping at the first error.
+    pub async fn each<F, Fut, Err>(&self, mut f: F) -> Result<(), Err>
+    where
+        F: FnMut(Vec<T>) -> Fut,
+        Fut: Future<Output = Result<(), Err>>,
+        Err: From<Error>,
+    {
+        let mut last = None;
+        loop {
+            let batch = self
+                .fetch(self.executor, last.as_ref().or(self.after.as_ref()))
+                .await?;
+            let Some(key) = batch.last().map(T::primary_key) else {
+                return Ok(());
+            };
+            let done = (batch.len() as i64) < self.size;
+            f(batch).await?;
+            if done {
+                return Ok(());
+            }
+            last = Some(key);
+        }
+    }
+
+    /// Like [`BatchClause::each`], with each batch in its own transaction or savepoint.
+    pub async fn each_in_transaction<F, Err>(&self, mut f: F) -> Result<(), Err>
+    where
+        F: for<'t> FnMut(&'t Atomic<'a>, Vec<T>) -> BoxFuture<'t, Result<(), Err>>,
+        Err: From<Error>,
+    {
+        let mut last = None;
+        loop {
+            let atomic = Atomic::begin(self.executor).await?;
+            let batch = self
+                .fetch(&atomic, last.as_ref().or(self.after.as_ref()))
+                .await?;
+            let Some(key) = batch.last().map(T::primary_key) else {
+                atomic.commit().await?;
+                return Ok(());
+            };
+            let done = (batch.len() as i64) < self.size;
+            match f(&atomic, batch).await {
+                Ok(()) => atomic.commit().await?,
+                Err(e) => {
+                    if let Err(err) = atomic.rollback().await {
+                        debug!("failed to rollback batch: {}", err);
+                    }
+                    return Err(e);
+                }
+            }
+            if done {
+                return Ok(());
+            }
+            last = Some(key);
+        }
+    }
+}
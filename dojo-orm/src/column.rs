@@ -0,0 +1,185 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::types::ToSql;
+
+/// A typed column of `M` holding `T`, generated by `#[derive(Model)]` as `User::columns()`.
+pub struct Column<M, T> {
+    name: &'static str,
+    _m: PhantomData<fn() -> (M, T)>,
+}
+
+impl<M, T> Column<M, T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _m: PhantomData,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<M, T> Clone for Column<M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, T> Copy for Column<M, T> {}
+
+impl<M, T> fmt::Debug for Column<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Column").field(&self.name).finish()
+    }
+}
+
+impl<M, T> fmt::Display for Column<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl<M, T> From<Column<M, T>> for &str {
+    fn from(column: Column<M, T>) -> Self {
+        column.name
+    }
+}
+
+impl<M, T> From<Column<M, T>> for String {
+    fn from(column: Column<M, T>) -> Self {
+        column.name.to_string()
+    }
+}
+
+/// Anything naming a column: a raw `&str`/`String` or a typed [`Column`].
+pub trait IntoColumn<'a> {
+    fn into_column(self) -> Cow<'a, str>;
+}
+
+impl<'a> IntoColumn<'a> for &'a str {
+    fn into_column(self) -> Cow<'a, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<'a> IntoColumn<'a> for String {
+    fn into_column(self) -> Cow<'a, str> {
+        Cow::Owned(self)
+    }
+}
+
+impl<'a, M, T> IntoColumn<'a> for Column<M, T> {
+    fn into_column(self) -> Cow<'a, str> {
+        Cow::Borrowed(self.name)
+    }
+}
+
+/// A column comparable with values of type `V`: a raw name, or a [`Column`] of that type.
+pub trait ColumnOf<'a, V: ?Sized>: IntoColumn<'a> {}
+
+impl<'a, V: ?Sized> ColumnOf<'a, V> for &'a str {}
+
+impl<'a, V: ?Sized> ColumnOf<'a, V> for String {}
+
+impl<'a, M, T> ColumnOf<'a, T> for Column<M, T> {}
+
+impl<'a, M, T> ColumnOf<'a, T> for Column<M, Option<T>> {}
+
+impl<'a, 'b, M> ColumnOf<'a, &'b str> for Column<M, String> {}
+
+impl<'a, 'b, M> ColumnOf<'a, &'b str> for Column<M, Option<String>> {}
+
+/// A list of values bound as a single Postgres array, as taken by `in_list` and `not_in`.
+pub trait SqlArray {
+    type Item;
+}
+
+impl<T> SqlArray for Vec<T> {
+    type Item = T;
+}
+
+impl<T, const N: usize> SqlArray for [T; N] {
+    type Item = T;
+}
+
+impl<T> SqlArray for &[T] {
+    type Item = T;
+}
+
+/// The Postgres type a value binds as, for casting the arrays `in_tuples` unnests.
+pub trait SqlType {
+    const SQL_TYPE: &'static str;
+}
+
+macro_rules! impl_sql_type {
+    ($($ty:ty => $name:literal),* $(,)?) => {
+        $(
+            impl SqlType for $ty {
+                const SQL_TYPE: &'static str = $name;
+            }
+        )*
+    };
+}
+
+impl_sql_type! {
+    bool => "bool",
+    i16 => "int2",
+    i32 => "int4",
+    i64 => "int8",
+    u32 => "oid",
+    f32 => "float4",
+    f64 => "float8",
+    String => "text",
+    &str => "text",
+    Vec<u8> => "bytea",
+    uuid::Uuid => "uuid",
+    chrono::NaiveDate => "date",
+    chrono::NaiveTime => "time",
+    chrono::NaiveDateTime => "timestamp",
+    chrono::DateTime<chrono::Utc> => "timestamptz",
+    serde_json::Value => "jsonb",
+    std::net::IpAddr => "inet",
+}
+
+impl<T: SqlType> SqlType for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+}
+
+/// An array of values owned by an op, bound as a single parameter.
+pub type SharedParam = Arc<dyn ToSql + Sync + Send>;
+
+/// A tuple whose fields are compared as a row value, as taken by `in_tuples`.
+pub trait SqlTuple: Sized {
+    const LEN: usize;
+
+    /// Splits `tuples` into one array per field, with each array's Postgres type.
+    fn unnest(tuples: &[Self]) -> Vec<(&'static str, SharedParam)>;
+}
+
+macro_rules! impl_sql_tuple {
+    ($len:literal; $($name:ident $index:tt),+) => {
+        impl<$($name),+> SqlTuple for ($($name,)+)
+        where
+            $($name: ToSql + SqlType + Clone + Sync + Send + 'static),+
+        {
+            const LEN: usize = $len;
+
+            fn unnest(tuples: &[Self]) -> Vec<(&'static str, SharedParam)> {
+                vec![$((
+                    $name::SQL_TYPE,
+                    Arc::new(tuples.iter().map(|t| t.$index.clone()).collect::<Vec<_>>())
+                        as SharedParam,
+                )),+]
+            }
+        }
+    };
+}
+
+impl_sql_tuple!(2; A 0, B 1);
+impl_sql_tuple!(3; A 0, B 1, C 2);
+impl_sql_tuple!(4; A 0, B 1, C 2, D 3);
@@ -0,0 +1,48 @@
+use std::borrow::Borrow;
+use std::pin::pin;
+
+use futures_util::{Stream, StreamExt};
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::ident::{columns, table};
+use crate::model::Model;
+
+/// Writes `rows` with a binary `COPY`, returning the number of rows copied.
+pub(crate) async fn copy_in<E, T, B, S>(executor: &E, rows: S) -> Result<u64>
+where
+    E: Executor,
+    T: Model,
+    B: Borrow<T>,
+    S: Stream<Item = B>,
+{
+    let client = executor.checkout().await?;
+
+    // the binary format needs every column's type up front; preparing a select of the
+    // columns gets them from the server without reading any rows
+    let query = format!("SELECT {} FROM {}", columns::<T>(), table::<T>());
+    debug!("query: {}", query);
+    let statement = client.prepare(&query).await?;
+    let types = statement
+        .columns()
+        .iter()
+        .map(|column| column.type_().clone())
+        .collect::<Vec<_>>();
+
+    let query = format!(
+        "COPY {} ({}) FROM STDIN (FORMAT binary)",
+        table::<T>(),
+        columns::<T>()
+    );
+    debug!("query: {}", query);
+    let sink = client.copy_in(&query).await?;
+    let mut writer = pin!(BinaryCopyInWriter::new(sink, &types));
+    let mut rows = pin!(rows);
+    while let Some(row) = rows.next().await {
+        writer.as_mut().write(&row.borrow().params()).await?;
+    }
+
+    Ok(writer.finish().await?)
+}
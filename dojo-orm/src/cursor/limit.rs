@@ -1,100 +1,245 @@
 use std::marker::PhantomData;
 
-use async_graphql::futures_util::TryFutureExt;
 use tracing::debug;
 
+use crate::cursor::{bounds_sql, keyset_sql, walk_orders};
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{columns, quote_ident, table};
 use crate::model::Model;
-use crate::ops::Op;
-use crate::order_by::Order;
-use crate::pagination::{Cursor, CursorExt, Pagination};
-use crate::pool::*;
+use crate::ops::{where_sql, Op};
+use crate::order_by::{order_by_sql, OrderBy};
+use crate::pagination::{CountMode, Cursor, CursorExt, Pagination};
 use crate::types::ToSql;
 
-pub struct CursorLimitClause<'a, T>
+/// The column `CountMode::Window` adds to every row of the page.
+const WINDOW_TOTAL: &str = "__total";
+
+pub struct CursorLimitClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub(crate) pool: &'a Pool<PostgresConnectionManager<NoTls>>,
+    pub(crate) executor: &'a E,
     pub(crate) params: &'a [&'a (dyn ToSql + Sync)],
     pub(crate) ops: &'a [Op<'a>],
-    pub(crate) orders: &'a [(&'a str, Order)],
+    pub(crate) orders: &'a [OrderBy<'a>],
     pub(crate) before: &'a Option<Cursor>,
     pub(crate) after: &'a Option<Cursor>,
-    pub(crate) limit: i64,
+    pub(crate) first: Option<usize>,
+    pub(crate) last: Option<usize>,
+    pub(crate) limit: usize,
+    pub(crate) count: CountMode,
     pub(crate) _t: PhantomData<T>,
 }
 
-impl<'a, T> CursorLimitClause<'a, T>
+impl<'a, T, E> CursorLimitClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub fn build(&'a self) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    /// Chooses how `total_nodes` is computed, defaulting to [`CountMode::Exact`].
+    pub fn count_mode(&'a mut self, count: CountMode) -> &'a mut Self {
+        self.count = count;
+        self
+    }
+
+    /// `first` and `last` clamped to `limit`, or `limit` rows from one end of the window.
+    fn page_sizes(&self) -> (Option<usize>, Option<usize>) {
+        match (self.first, self.last) {
+            (None, None) if self.before.is_some() && self.after.is_none() => {
+                (None, Some(self.limit))
+            }
+            (None, None) => (Some(self.limit), None),
+            (first, last) => (
+                first.map(|n| n.min(self.limit)),
+                last.map(|n| n.min(self.limit)),
+            ),
+        }
+    }
+
+    fn backwards(&self) -> bool {
+        let (first, last) = self.page_sizes();
+        first.is_none() && last.is_some()
+    }
+
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
         let mut params_index = 1;
 
         let mut params = self.params.to_vec();
         let mut query = "SELECT ".to_string();
-        query.push_str(&T::COLUMNS.join(", "));
-        query.push_str(" FROM ");
-        query.push_str(T::NAME);
-
-        let mut ands = vec![];
-        for op in self.ops {
-            let (q, p) = op.sql(&mut params_index);
-            ands.push(q);
-            params.extend_from_slice(&p);
-        }
-        if !ands.is_empty() {
-            let and = ands.join(" AND ");
-            query.push_str(" WHERE ");
-            query.push_str(&and);
-        }
+        query.push_str(&columns::<T>());
 
-        query.push_str(" ORDER BY ");
-        if self.before.is_some() {
-            query.push_str("created_at ASC");
+        let orders = walk_orders(self.orders, false);
+        if self.count == CountMode::Window {
+            // count in a subquery without the cursor bounds, so the total covers the
+            // whole connection rather than the rows past the cursor
+            let filters = where_sql::<T>(self.ops, &mut params_index, &mut params)?;
+            query.push_str(&format!(
+                ", {} FROM (SELECT {}, COUNT(*) OVER() AS {} FROM {}{}) AS \"page\"",
+                quote_ident(WINDOW_TOTAL),
+                columns::<T>(),
+                quote_ident(WINDOW_TOTAL),
+                table::<T>(),
+                filters
+            ));
+            let (bounds, p) = bounds_sql::<T>(&orders, self.before, self.after, &mut params_index)?;
+            if !bounds.is_empty() {
+                query.push_str(" WHERE ");
+                query.push_str(&bounds.join(" AND "));
+            }
+            params.extend(p);
         } else {
-            query.push_str("created_at DESC");
+            query.push_str(" FROM ");
+            query.push_str(&table::<T>());
+            let filters = where_sql::<T>(self.ops, &mut params_index, &mut params)?;
+            query.push_str(&filters);
+            let (bounds, p) = bounds_sql::<T>(&orders, self.before, self.after, &mut params_index)?;
+            if !bounds.is_empty() {
+                query.push_str(if filters.is_empty() {
+                    " WHERE "
+                } else {
+                    " AND "
+                });
+                query.push_str(&bounds.join(" AND "));
+            }
+            params.extend(p);
         }
 
-        let limit = self.limit + if self.after.is_some() { 2 } else { 1 };
+        let orders = walk_orders(self.orders, self.backwards());
+        query.push_str(&order_by_sql::<T>(&orders)?);
+
+        // one row past the page tells whether the window holds more; with both `first`
+        // and `last`, enough rows to compare the window against each
+        let (first, last) = self.page_sizes();
+        let limit = first.max(last).unwrap_or_default() + 1;
         query.push_str(format!(" LIMIT {}", limit).as_str());
 
-        (query, params)
+        Ok((query, params))
+    }
+
+    async fn count(&'a self, count: CountMode) -> Result<Option<i64>> {
+        let mut params = self.params.to_vec();
+        let filters = where_sql::<T>(self.ops, &mut 1, &mut params)?;
+
+        let total = match count {
+            CountMode::Skip => return Ok(None),
+            // `Window` reads the total off the page, this only runs when the page is empty
+            CountMode::Exact | CountMode::Window => {
+                let query = format!("SELECT COUNT(*) FROM {}{}", table::<T>(), filters);
+                debug!("query: {}, params: {:?}", query, params);
+                let row = self.executor.query_one(query.as_str(), &params).await?;
+                row.try_get(0).map_err(|e| Error::decode("count", e))?
+            }
+            CountMode::Estimated => {
+                let query = format!(
+                    "EXPLAIN (FORMAT JSON) SELECT 1 FROM {}{}",
+                    table::<T>(),
+                    filters
+                );
+                debug!("query: {}, params: {:?}", query, params);
+                let row = self.executor.query_one(query.as_str(), &params).await?;
+                let plan: serde_json::Value =
+                    row.try_get(0).map_err(|e| Error::decode("QUERY PLAN", e))?;
+                plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or_default() as i64
+            }
+        };
+
+        Ok(Some(total))
     }
 
-    async fn count(&'a self) -> anyhow::Result<i64> {
-        let query = format!("SELECT COUNT(*) FROM {}", T::NAME);
-        let conn = self.pool.get().await?;
-        let row = conn.query_one(query.as_str(), &[]).await?;
+    /// Whether any row matching the filters sits at `cursor` or past it, walking `orders`.
+    async fn exists(
+        &'a self,
+        orders: Vec<OrderBy<'a>>,
+        cursor: Option<&'a Cursor>,
+    ) -> Result<bool> {
+        let Some(cursor) = cursor else {
+            return Ok(false);
+        };
+
+        let mut params_index = 1;
+        let mut params = self.params.to_vec();
+        let filters = where_sql::<T>(self.ops, &mut params_index, &mut params)?;
+        let (bound, p) = keyset_sql::<T>(&orders, cursor, true, &mut params_index)?;
+        params.extend(p);
+
+        let query = format!(
+            "SELECT EXISTS(SELECT 1 FROM {}{}{}{})",
+            table::<T>(),
+            filters,
+            if filters.is_empty() {
+                " WHERE "
+            } else {
+                " AND "
+            },
+            bound
+        );
+        debug!("query: {}, params: {:?}", query, params);
+        let row = self.executor.query_one(query.as_str(), &params).await?;
 
-        row.try_get(0)
-            .map_err(|e| anyhow::anyhow!("failed to get count: {}", e))
+        row.try_get(0).map_err(|e| Error::decode("exists", e))
     }
 
-    pub async fn all<T1>(&'a self) -> anyhow::Result<Pagination<Cursor, T1>>
+    /// Fetches the page, with the Relay spec's `hasPreviousPage` and `hasNextPage`.
+    pub async fn all<T1>(&'a self) -> Result<Pagination<Cursor, T1>>
     where
         T1: CursorExt<Cursor> + Model + Sync + Send,
     {
-        let (query, params) = self.build();
-        debug!("query: {}, params: {:?}", query, params);
-        let conn = self.pool.get().await?;
+        let (first, last) = self.page_sizes();
+        let backwards = self.backwards();
 
-        let query_fut = conn
-            .query(query.as_str(), &params)
-            .map_err(|e| anyhow::anyhow!("failed to query: {}", e));
-        let (rows, count) = tokio::try_join!(query_fut, self.count())?;
+        // the fetched rows only see one side of the page: paging forwards without `last`,
+        // whether rows precede `after`; paging backwards, whether rows follow `before`
+        let probe = if backwards {
+            self.exists(walk_orders(self.orders, false), self.before.as_ref())
+        } else if last.is_none() {
+            self.exists(walk_orders(self.orders, true), self.after.as_ref())
+        } else {
+            self.exists(vec![], None)
+        };
+
+        let (query, params) = self.build()?;
+        debug!("query: {}, params: {:?}", query, params);
+        let query_fut = self.executor.query(query.as_str(), &params);
+        let (rows, count, probe) = if self.count == CountMode::Window {
+            let (rows, probe) = tokio::try_join!(query_fut, probe)?;
+            let count = match rows.first() {
+                Some(row) => Some(
+                    row.try_get(WINDOW_TOTAL)
+                        .map_err(|e| Error::decode(WINDOW_TOTAL, e))?,
+                ),
+                None => self.count(CountMode::Window).await?,
+            };
+            (rows, count, probe)
+        } else {
+            tokio::try_join!(query_fut, self.count(self.count), probe)?
+        };
 
         let mut items = vec![];
         for row in rows {
             items.push(T1::from_row(row)?);
         }
 
-        Ok(Pagination::new(
-            items,
-            self.before.clone(),
-            self.after.clone(),
-            self.limit,
-            count,
-        ))
+        let fetched = items.len();
+        let (has_previous, has_next) = if backwards {
+            let last = last.unwrap_or_default();
+            items.truncate(last);
+            items.reverse();
+            (fetched > last, probe)
+        } else {
+            let first = first.unwrap_or_default();
+            items.truncate(first);
+            let has_previous = match last {
+                Some(last) => {
+                    items.drain(..items.len().saturating_sub(last));
+                    fetched > last
+                }
+                None => probe,
+            };
+            (has_previous, fetched > first)
+        };
+
+        Ok(Pagination::new(items, has_previous, has_next, count))
     }
 }
@@ -1,2 +1,111 @@
 pub mod limit;
 pub mod order_by;
+
+use crate::error::Result;
+use crate::model::Model;
+use crate::order_by::{Order, OrderBy};
+use crate::pagination::{Cursor, OffsetEncodedError};
+use crate::types::ToSql;
+
+/// The order used when none is given with `order_by`.
+const DEFAULT_ORDER: OrderBy<'static> = OrderBy {
+    column: "created_at",
+    order: Order::Desc,
+    nulls: None,
+    collate: None,
+};
+
+/// The orders a cursor query walks in, reversed when paging backwards.
+pub(crate) fn walk_orders<'a>(orders: &[OrderBy<'a>], backwards: bool) -> Vec<OrderBy<'a>> {
+    let orders = if orders.is_empty() {
+        vec![DEFAULT_ORDER]
+    } else {
+        orders.to_vec()
+    };
+
+    if backwards {
+        orders.iter().map(OrderBy::reverse).collect()
+    } else {
+        orders
+    }
+}
+
+/// Renders the condition for rows past `cursor` walking `orders`, or at it when `inclusive`.
+pub(crate) fn keyset_sql<'a, T: Model>(
+    orders: &[OrderBy],
+    cursor: &'a Cursor,
+    inclusive: bool,
+    params_index: &mut usize,
+) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+    let mut columns = vec![];
+    let mut placeholders = vec![];
+    let mut params: Vec<&'a (dyn ToSql + Sync)> = vec![];
+    for order in orders {
+        order.validate::<T>()?;
+        let value = cursor
+            .value(order.column)
+            .ok_or(OffsetEncodedError::InvalidCursor)?;
+        columns.push(order.column_sql());
+        placeholders.push(format!("${}", params_index));
+        params.push(value);
+        *params_index += 1;
+    }
+
+    let op = |order: Order, strict: bool| match (order, strict) {
+        (Order::Asc, true) => ">",
+        (Order::Asc, false) => ">=",
+        (Order::Desc, true) => "<",
+        (Order::Desc, false) => "<=",
+    };
+
+    let query = if orders.windows(2).all(|w| w[0].order == w[1].order) {
+        format!(
+            "({}) {} ({})",
+            columns.join(", "),
+            op(orders[0].order, !inclusive),
+            placeholders.join(", ")
+        )
+    } else {
+        let mut ors = vec![];
+        for (i, order) in orders.iter().enumerate() {
+            let mut ands = (0..i)
+                .map(|j| format!("{} = {}", columns[j], placeholders[j]))
+                .collect::<Vec<_>>();
+            let strict = !inclusive || i + 1 < orders.len();
+            ands.push(format!(
+                "{} {} {}",
+                columns[i],
+                op(order.order, strict),
+                placeholders[i]
+            ));
+            ors.push(format!("({})", ands.join(" AND ")));
+        }
+        format!("({})", ors.join(" OR "))
+    };
+
+    Ok((query, params))
+}
+
+/// The keyset conditions for `after` and `before`, both excluding the cursor row.
+pub(crate) fn bounds_sql<'a, T: Model>(
+    orders: &[OrderBy],
+    before: &'a Option<Cursor>,
+    after: &'a Option<Cursor>,
+    params_index: &mut usize,
+) -> Result<(Vec<String>, Vec<&'a (dyn ToSql + Sync)>)> {
+    let mut bounds = vec![];
+    let mut params = vec![];
+    if let Some(after) = after {
+        let (q, p) = keyset_sql::<T>(orders, after, false, params_index)?;
+        bounds.push(q);
+        params.extend(p);
+    }
+    if let Some(before) = before {
+        let reversed = orders.iter().map(OrderBy::reverse).collect::<Vec<_>>();
+        let (q, p) = keyset_sql::<T>(&reversed, before, false, params_index)?;
+        bounds.push(q);
+        params.extend(p);
+    }
+
+    Ok((bounds, params))
+}
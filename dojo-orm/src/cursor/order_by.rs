@@ -1,77 +1,102 @@
 use std::marker::PhantomData;
 
+use futures_util::Stream;
+
 use crate::cursor::limit::CursorLimitClause;
+use crate::cursor::{bounds_sql, walk_orders};
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::ident::{columns, table};
 use crate::model::Model;
-use crate::ops::Op;
-use crate::order_by::Order;
-use crate::pagination::Cursor;
-use crate::pool::*;
+use crate::ops::{where_sql, Op};
+use crate::order_by::{order_by_sql, OrderBy};
+use crate::pagination::{CountMode, Cursor};
+use crate::stream::query_stream;
 use crate::types::ToSql;
 
-pub struct CursorOrderByClause<'a, T>
+pub struct CursorOrderByClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub(crate) pool: &'a Pool<PostgresConnectionManager<NoTls>>,
+    pub(crate) executor: &'a E,
     pub(crate) params: &'a [&'a (dyn ToSql + Sync)],
     pub(crate) ops: &'a [Op<'a>],
-    pub(crate) orders: Vec<(&'a str, Order)>,
-    pub(crate) before: &'a Option<Cursor>,
-    pub(crate) after: &'a Option<Cursor>,
+    pub(crate) orders: Vec<OrderBy<'a>>,
+    pub(crate) before: Option<Cursor>,
+    pub(crate) after: Option<Cursor>,
+    pub(crate) first: Option<usize>,
+    pub(crate) last: Option<usize>,
     pub(crate) _t: PhantomData<T>,
 }
 
-impl<'a, T> CursorOrderByClause<'a, T>
+impl<'a, T, E> CursorOrderByClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub fn order_by(&'a mut self, order: (&'a str, Order)) -> &'a mut Self {
+    pub fn order_by(&'a mut self, order: OrderBy<'a>) -> &'a mut Self {
         self.orders.push(order);
         self
     }
 
-    pub fn limit(&'a mut self, limit: i64) -> CursorLimitClause<'a, T> {
+    /// Caps the page size, which is `limit` without `first` or `last`.
+    pub fn limit(&'a mut self, limit: i64) -> CursorLimitClause<'a, T, E> {
         CursorLimitClause {
-            pool: &self.pool,
-            params: &self.params,
-            ops: &self.ops,
+            executor: self.executor,
+            params: self.params,
+            ops: self.ops,
             orders: &self.orders,
-            limit,
-            before: self.before,
-            after: self.after,
+            count: CountMode::default(),
+            limit: limit.max(0) as usize,
+            before: &self.before,
+            after: &self.after,
+            first: self.first,
+            last: self.last,
             _t: PhantomData,
         }
     }
 
-    pub fn build(&'a self) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    /// Whether the page is taken from the end of the cursor window.
+    pub(crate) fn backwards(&self) -> bool {
+        match (self.first, self.last) {
+            (None, None) => self.before.is_some() && self.after.is_none(),
+            (first, last) => first.is_none() && last.is_some(),
+        }
+    }
+
+    /// Streams every row past the cursors, in order, rather than a single page.
+    pub fn stream(&'a self) -> impl Stream<Item = Result<T>> + 'a {
+        query_stream(self.executor, self.build())
+    }
+
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
         let mut params_index = 1;
 
         let mut params = self.params.to_vec();
         let mut query = "SELECT ".to_string();
-        query.push_str(&T::COLUMNS.join(", "));
+        query.push_str(&columns::<T>());
         query.push_str(" FROM ");
-        query.push_str(T::NAME);
+        query.push_str(&table::<T>());
 
-        let mut ands = vec![];
-        for op in self.ops {
-            let (q, p) = op.sql(&mut params_index);
-            ands.push(q);
-            params.extend_from_slice(&p);
-        }
+        let filters = where_sql::<T>(self.ops, &mut params_index, &mut params)?;
+        query.push_str(&filters);
 
-        if !query.is_empty() {
-            let and = ands.join(" AND ");
-            query.push_str(" WHERE ");
-            query.push_str(&and);
+        let orders = walk_orders(&self.orders, false);
+        let (bounds, p) = bounds_sql::<T>(&orders, &self.before, &self.after, &mut params_index)?;
+        if !bounds.is_empty() {
+            query.push_str(if filters.is_empty() {
+                " WHERE "
+            } else {
+                " AND "
+            });
+            query.push_str(&bounds.join(" AND "));
         }
+        params.extend(p);
 
-        query.push_str(" ORDER BY ");
-        if self.before.is_some() {
-            query.push_str("created_at ASC");
-        } else {
-            query.push_str("created_at DESC");
-        }
+        let orders = walk_orders(&self.orders, self.backwards());
+        query.push_str(&order_by_sql::<T>(&orders)?);
 
-        (query, params)
+        Ok((query, params))
     }
 }
@@ -1,134 +1,397 @@
-use std::marker::PhantomData;
-use tracing::debug;
+use std::fmt;
+use std::time::Duration;
 
-use crate::model::{Model, UpdateModel};
+use async_trait::async_trait;
+use bb8::{Builder, CustomizeConnection};
+use futures_util::Stream;
+use tokio_postgres::tls::MakeTlsConnect;
+use tokio_postgres::{Client, Error, Row, Socket};
+
+use crate::error::Result;
+use crate::executor::{ClientGuard, Executor};
+use crate::model::{Model, PrimaryKey, UpdateModel};
 use crate::pagination::{Cursor, CursorExt};
 use crate::pool::*;
+#[cfg(feature = "rustls")]
+use crate::tls::MakeRustlsConnect;
+#[cfg(feature = "openssl")]
+use crate::tls::MakeTlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls"))]
+use crate::tls::TlsConfig;
+use crate::transaction::{BoxFuture, Transaction, TransactionBuilder};
+use crate::types::ToSql;
+use crate::upsert::UpsertClause;
 use crate::where_select::WhereSelectClause;
 use crate::where_update::WhereUpdateClause;
 
 #[derive(Clone)]
-pub struct Database {
-    pool: Pool<PostgresConnectionManager<NoTls>>,
+pub struct Database<Tls = NoTls>
+where
+    Tls: MakeTlsConnect<Socket>,
+    PostgresConnectionManager<Tls>: ManageConnection,
+{
+    pub(crate) pool: Pool<PostgresConnectionManager<Tls>>,
 }
 
 impl Database {
-    pub async fn new(url: &str) -> anyhow::Result<Self> {
-        let manager = PostgresConnectionManager::new_from_stringlike(url, NoTls)?;
-        let pool = Pool::builder().build(manager).await?;
+    pub async fn new(url: &str) -> Result<Self> {
+        Self::builder().build(url).await
+    }
+
+    pub fn builder() -> DatabaseBuilder {
+        DatabaseBuilder::default()
+    }
+}
 
-        Ok(Self { pool })
+#[cfg(feature = "openssl")]
+impl Database<MakeTlsConnector> {
+    /// Connects over TLS using OpenSSL, honouring the libpq TLS parameters of `url`.
+    pub async fn new_tls(url: &str) -> Result<Self> {
+        Database::builder().build_tls(url).await
     }
 
-    pub async fn get(&self) -> anyhow::Result<PooledConnection<PostgresConnectionManager<NoTls>>> {
-        Ok(self.pool.get().await?)
+    /// Connects over TLS using OpenSSL, with `config` in place of the TLS parameters of `url`.
+    pub async fn new_tls_with(url: &str, config: TlsConfig) -> Result<Self> {
+        Database::builder().build_tls_with(url, config).await
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl Database<MakeRustlsConnect> {
+    /// Connects over TLS using rustls, honouring the libpq TLS parameters of `url`.
+    pub async fn new_rustls(url: &str) -> Result<Self> {
+        Database::builder().build_rustls(url).await
+    }
+
+    /// Connects over TLS using rustls, with `config` in place of the TLS parameters of `url`.
+    pub async fn new_rustls_with(url: &str, config: TlsConfig) -> Result<Self> {
+        Database::builder().build_rustls_with(url, config).await
+    }
+}
+
+pub type AfterConnect =
+    dyn for<'c> Fn(&'c mut Client) -> BoxFuture<'c, Result<(), Error>> + Send + Sync;
+
+/// bb8's default `max_size`.
+const DEFAULT_MAX_SIZE: u32 = 10;
+
+/// Configures the connection pool behind a [`Database`].
+#[derive(Default)]
+pub struct DatabaseBuilder {
+    max_size: Option<u32>,
+    min_idle: Option<Option<u32>>,
+    connection_timeout: Option<Duration>,
+    idle_timeout: Option<Option<Duration>>,
+    max_lifetime: Option<Option<Duration>>,
+    test_on_check_out: Option<bool>,
+    after_connect: Option<Box<AfterConnect>>,
+}
+
+impl DatabaseBuilder {
+    /// Maximum number of connections managed by the pool, at least 1. Defaults to 10.
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Idle connections the pool keeps open, at most `max_size`. Defaults to none.
+    pub fn min_idle(mut self, min_idle: impl Into<Option<u32>>) -> Self {
+        self.min_idle = Some(min_idle.into());
+        self
+    }
+
+    /// How long checking out a connection may wait, more than zero. Defaults to 30 seconds.
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> Self {
+        self.connection_timeout = Some(connection_timeout);
+        self
     }
 
-    pub fn bind<T>(&self) -> WhereSelectClause<T>
+    /// Closes connections idle this long. Defaults to 10 minutes; `None` keeps them open.
+    pub fn idle_timeout(mut self, idle_timeout: impl Into<Option<Duration>>) -> Self {
+        self.idle_timeout = Some(idle_timeout.into());
+        self
+    }
+
+    /// Closes connections this old. Defaults to 30 minutes; `None` disables it.
+    pub fn max_lifetime(mut self, max_lifetime: impl Into<Option<Duration>>) -> Self {
+        self.max_lifetime = Some(max_lifetime.into());
+        self
+    }
+
+    /// Checks connections with a trivial query before handing them out. Defaults to `true`.
+    pub fn test_on_check_out(mut self, test_on_check_out: bool) -> Self {
+        self.test_on_check_out = Some(test_on_check_out);
+        self
+    }
+
+    /// Runs `f` on every new connection before it joins the pool, e.g. to set `search_path`.
+    pub fn after_connect<F>(mut self, f: F) -> Self
     where
-        T: Model + CursorExt<Cursor> + Sync + Send,
+        F: for<'c> Fn(&'c mut Client) -> BoxFuture<'c, Result<(), Error>> + Send + Sync + 'static,
     {
-        WhereSelectClause {
-            pool: &self.pool,
-            is_delete: false,
-            params: vec![],
-            ops: vec![],
-            _t: PhantomData,
-        }
+        self.after_connect = Some(Box::new(f));
+        self
     }
 
-    pub async fn insert<T: Model>(&self, data: &T) -> anyhow::Result<T> {
-        let mut query = "INSERT INTO ".to_string();
-        query.push_str(T::NAME);
-        query.push_str(" (");
-        query.push_str(T::COLUMNS.join(", ").as_str());
-        query.push_str(") VALUES (");
-
-        let mut params_index = 1;
-        let mut params = vec![];
-        let mut values = vec![];
-        for param in data.params() {
-            values.push(format!("${}", params_index));
-            params.push(param);
-            params_index += 1;
-        }
-        query.push_str(values.join(", ").as_str());
-        query.push_str(") RETURNING ");
-        query.push_str(T::COLUMNS.join(", ").as_str());
+    pub async fn build(self, url: &str) -> Result<Database> {
+        let manager = PostgresConnectionManager::new_from_stringlike(url, NoTls)?;
+        self.build_with(manager).await
+    }
 
-        let conn = self.pool.get().await?;
-        debug!("query: {}, params: {:?}", query, params);
-        let row = conn.query_one(query.as_str(), &params).await?;
+    /// Connects over TLS using OpenSSL, honouring the TLS parameters of `url`.
+    #[cfg(feature = "openssl")]
+    pub async fn build_tls(self, url: &str) -> Result<Database<MakeTlsConnector>> {
+        let config = TlsConfig::from_url(url)?;
+        self.build_tls_with(url, config).await
+    }
 
-        Ok(T::from_row(row)?)
+    #[cfg(feature = "openssl")]
+    pub async fn build_tls_with(
+        self,
+        url: &str,
+        config: TlsConfig,
+    ) -> Result<Database<MakeTlsConnector>> {
+        let manager =
+            PostgresConnectionManager::new(config.postgres_config(url)?, config.openssl()?);
+        self.build_with(manager).await
     }
 
-    pub async fn insert_many<T: Model>(&self, data: &[T]) -> anyhow::Result<Vec<T>> {
-        if data.is_empty() {
-            return Ok(vec![]);
-        }
+    /// Connects over TLS using rustls, honouring the TLS parameters of `url`.
+    #[cfg(feature = "rustls")]
+    pub async fn build_rustls(self, url: &str) -> Result<Database<MakeRustlsConnect>> {
+        let config = TlsConfig::from_url(url)?;
+        self.build_rustls_with(url, config).await
+    }
 
-        let mut query = "INSERT INTO ".to_string();
-        query.push_str(T::NAME);
-        query.push_str(" (");
-        query.push_str(T::COLUMNS.join(", ").as_str());
-        query.push_str(") VALUES ");
-
-        let mut params_index = 1;
-        let mut params = vec![];
-        let mut list_values = vec![];
-        for item in data {
-            let mut values = vec![];
-            let mut query = "(".to_string();
-            for param in item.params() {
-                values.push(format!("${}", params_index));
-                params.push(param);
-                params_index += 1;
+    #[cfg(feature = "rustls")]
+    pub async fn build_rustls_with(
+        self,
+        url: &str,
+        config: TlsConfig,
+    ) -> Result<Database<MakeRustlsConnect>> {
+        let manager =
+            PostgresConnectionManager::new(config.postgres_config(url)?, config.rustls()?);
+        self.build_with(manager).await
+    }
+
+    /// Rejects the settings bb8's `Builder` would panic on.
+    fn validate(&self) -> Result<()> {
+        let max_size = self.max_size.unwrap_or(DEFAULT_MAX_SIZE);
+        if max_size == 0 {
+            return Err(crate::Error::Config(
+                "max_size must be at least 1".to_string(),
+            ));
+        }
+        if let Some(Some(min_idle)) = self.min_idle {
+            if min_idle > max_size {
+                return Err(crate::Error::Config(format!(
+                    "min_idle ({}) must not exceed max_size ({})",
+                    min_idle, max_size
+                )));
             }
-            query.push_str(values.join(", ").as_str());
-            query.push_str(")");
+        }
+        let zero = Some(Duration::ZERO);
+        if self.connection_timeout == zero {
+            return Err(crate::Error::Config(
+                "connection_timeout must be greater than zero".to_string(),
+            ));
+        }
+        if self.idle_timeout.flatten() == zero {
+            return Err(crate::Error::Config(
+                "idle_timeout must be greater than zero".to_string(),
+            ));
+        }
+        if self.max_lifetime.flatten() == zero {
+            return Err(crate::Error::Config(
+                "max_lifetime must be greater than zero".to_string(),
+            ));
+        }
 
-            list_values.push(query);
+        Ok(())
+    }
+
+    async fn build_with<Tls>(self, manager: PostgresConnectionManager<Tls>) -> Result<Database<Tls>>
+    where
+        Tls: MakeTlsConnect<Socket>,
+        PostgresConnectionManager<Tls>: ManageConnection<Connection = Client, Error = Error>,
+    {
+        self.validate()?;
+
+        let mut builder: Builder<PostgresConnectionManager<Tls>> = Pool::builder();
+        if let Some(max_size) = self.max_size {
+            builder = builder.max_size(max_size);
+        }
+        if let Some(min_idle) = self.min_idle {
+            builder = builder.min_idle(min_idle);
+        }
+        if let Some(connection_timeout) = self.connection_timeout {
+            builder = builder.connection_timeout(connection_timeout);
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            builder = builder.idle_timeout(idle_timeout);
         }
-        query.push_str(list_values.join(", ").as_str());
-        query.push_str(" RETURNING ");
-        query.push_str(T::COLUMNS.join(", ").as_str());
+        if let Some(max_lifetime) = self.max_lifetime {
+            builder = builder.max_lifetime(max_lifetime);
+        }
+        if let Some(test_on_check_out) = self.test_on_check_out {
+            builder = builder.test_on_check_out(test_on_check_out);
+        }
+        if let Some(after_connect) = self.after_connect {
+            builder = builder.connection_customizer(Box::new(Customizer(after_connect)));
+        }
+
+        let pool = builder.build(manager).await?;
+
+        Ok(Database { pool })
+    }
+}
 
-        let conn = self.pool.get().await?;
-        debug!("query: {}, params: {:?}", query, params);
-        let row = conn.query(query.as_str(), &params).await?;
+struct Customizer(Box<AfterConnect>);
 
-        let mut rows = vec![];
-        for row in row {
-            rows.push(T::from_row(row)?);
+impl fmt::Debug for Customizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Customizer").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl CustomizeConnection<Client, Error> for Customizer {
+    async fn on_acquire(&self, conn: &mut Client) -> Result<(), Error> {
+        (self.0)(conn).await
+    }
+}
+
+impl<Tls> Database<Tls>
+where
+    Tls: MakeTlsConnect<Socket>,
+    PostgresConnectionManager<Tls>: ManageConnection<Connection = Client, Error = Error>,
+{
+    pub async fn get(&self) -> Result<PooledConnection<PostgresConnectionManager<Tls>>> {
+        Ok(self.pool.get().await?)
+    }
+
+    pub fn build_transaction(&self) -> TransactionBuilder<Tls> {
+        TransactionBuilder {
+            db: self,
+            isolation_level: None,
+            read_only: None,
+            deferrable: None,
         }
+    }
+
+    pub async fn begin(&self) -> Result<Transaction> {
+        self.build_transaction().begin().await
+    }
+
+    /// Runs `f` inside a transaction, committing when it returns `Ok`.
+    pub async fn transaction<F, R, E>(&self, f: F) -> Result<R, E>
+    where
+        F: for<'t> FnOnce(&'t mut Transaction) -> BoxFuture<'t, Result<R, E>>,
+        E: From<crate::Error>,
+    {
+        self.build_transaction().run(f).await
+    }
+
+    pub fn bind<T>(&self) -> WhereSelectClause<T, Pool<PostgresConnectionManager<Tls>>>
+    where
+        T: Model + CursorExt<Cursor> + Sync + Send,
+    {
+        self.pool.bind()
+    }
+
+    pub async fn insert<T: Model + Sync>(&self, data: &T) -> Result<T> {
+        self.pool.insert(data).await
+    }
+
+    pub async fn insert_many<T: Model + Sync>(&self, data: &[T]) -> Result<Vec<T>> {
+        self.pool.insert_many(data).await
+    }
+
+    pub async fn copy_in<T: Model + Sync>(&self, data: &[T]) -> Result<u64> {
+        self.pool.copy_in(data).await
+    }
+
+    pub async fn copy_in_stream<T, S>(&self, rows: S) -> Result<u64>
+    where
+        T: Model + Send + Sync,
+        S: Stream<Item = T> + Send,
+    {
+        self.pool.copy_in_stream(rows).await
+    }
+
+    pub fn upsert<'a, T: Model>(
+        &'a self,
+        data: &'a T,
+    ) -> UpsertClause<'a, T, Pool<PostgresConnectionManager<Tls>>> {
+        self.pool.upsert(data)
+    }
 
-        Ok(rows)
+    pub fn upsert_many<'a, T: Model>(
+        &'a self,
+        data: &'a [T],
+    ) -> UpsertClause<'a, T, Pool<PostgresConnectionManager<Tls>>> {
+        self.pool.upsert_many(data)
     }
 
     pub fn update<'a, T: Model, U: UpdateModel>(
         &'a self,
         data: &'a U,
-    ) -> WhereUpdateClause<'a, T, U> {
-        let params = data.params();
-        let columns = data.columns();
-        WhereUpdateClause {
-            pool: &self.pool,
-            columns: columns.clone(),
-            params: params.clone(),
-            ops: vec![],
-            _t: PhantomData,
-            _u: PhantomData,
-        }
+    ) -> WhereUpdateClause<'a, T, U, Pool<PostgresConnectionManager<Tls>>> {
+        self.pool.update(data)
     }
 
-    pub fn delete<T: Model>(&self) -> WhereSelectClause<T> {
-        WhereSelectClause {
-            pool: &self.pool,
-            is_delete: true,
-            params: vec![],
-            ops: vec![],
-            _t: PhantomData,
-        }
+    pub fn delete<T: Model>(&self) -> WhereSelectClause<T, Pool<PostgresConnectionManager<Tls>>> {
+        self.pool.delete()
+    }
+
+    pub async fn find<T: PrimaryKey>(&self, id: &T::Key) -> Result<Option<T>> {
+        self.pool.find(id).await
+    }
+
+    pub async fn find_many<T: PrimaryKey>(&self, ids: &[T::Key]) -> Result<Vec<T>> {
+        self.pool.find_many(ids).await
+    }
+
+    pub async fn update_by_id<T: PrimaryKey, U: UpdateModel + Sync>(
+        &self,
+        id: &T::Key,
+        data: &U,
+    ) -> Result<Option<T>> {
+        self.pool.update_by_id(id, data).await
+    }
+
+    pub async fn delete_by_id<T: PrimaryKey>(&self, id: &T::Key) -> Result<Option<T>> {
+        self.pool.delete_by_id(id).await
+    }
+
+    pub async fn save<T: PrimaryKey + Sync>(&self, data: &T) -> Result<T> {
+        self.pool.save(data).await
+    }
+}
+
+#[async_trait]
+impl<Tls> Executor for Database<Tls>
+where
+    Tls: MakeTlsConnect<Socket>,
+    PostgresConnectionManager<Tls>: ManageConnection<Connection = Client, Error = Error>,
+{
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>> {
+        Executor::query(&self.pool, query, params).await
+    }
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>> {
+        Executor::query_opt(&self.pool, query, params).await
+    }
+
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row> {
+        Executor::query_one(&self.pool, query, params).await
+    }
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64> {
+        Executor::execute(&self.pool, query, params).await
+    }
+
+    async fn checkout(&self) -> Result<ClientGuard<'_>> {
+        self.pool.checkout().await
     }
 }
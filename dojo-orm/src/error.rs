@@ -0,0 +1,205 @@
+#[cfg(feature = "graphql")]
+use async_graphql::ErrorExtensions;
+use bb8::RunError;
+use thiserror::Error;
+use tokio_postgres::error::SqlState;
+
+use crate::pagination::OffsetEncodedError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("record not found")]
+    NotFound,
+
+    #[error("unique constraint {constraint} violated")]
+    UniqueViolation {
+        constraint: String,
+        #[source]
+        source: tokio_postgres::Error,
+    },
+
+    #[error("foreign key constraint {constraint} violated")]
+    ForeignKeyViolation {
+        constraint: String,
+        #[source]
+        source: tokio_postgres::Error,
+    },
+
+    #[error("check constraint {constraint} violated")]
+    CheckViolation {
+        constraint: String,
+        #[source]
+        source: tokio_postgres::Error,
+    },
+
+    #[error("null value in column {column} violates not-null constraint")]
+    NotNullViolation {
+        column: String,
+        #[source]
+        source: tokio_postgres::Error,
+    },
+
+    /// The transaction could not be serialized and can be retried.
+    #[error("could not serialize access due to concurrent update")]
+    SerializationFailure(#[source] tokio_postgres::Error),
+
+    /// The transaction was aborted to break a deadlock and can be retried.
+    #[error("deadlock detected")]
+    DeadlockDetected(#[source] tokio_postgres::Error),
+
+    #[error("timed out waiting for a connection from the pool")]
+    PoolTimeout,
+
+    #[error("failed to decode column {column}")]
+    Decode {
+        column: String,
+        #[source]
+        source: tokio_postgres::Error,
+    },
+
+    #[error("unknown column {column} on {table}")]
+    UnknownColumn { table: &'static str, column: String },
+
+    /// A row comparison or `in_tuples` with a different number of columns and values.
+    #[error("{columns} columns compared with {values} values")]
+    ColumnCount { columns: usize, values: usize },
+
+    /// A pagination cursor that can't be decoded or doesn't fit the query's order.
+    #[error(transparent)]
+    Cursor(#[from] OffsetEncodedError),
+
+    #[error("tls error: {0}")]
+    Tls(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    /// A query builder used in a way that can't produce valid SQL.
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error(transparent)]
+    Postgres(tokio_postgres::Error),
+}
+
+impl Error {
+    pub(crate) fn decode(column: &str, source: tokio_postgres::Error) -> Self {
+        Error::Decode {
+            column: column.to_string(),
+            source,
+        }
+    }
+
+    #[cfg(any(feature = "openssl", feature = "rustls"))]
+    pub(crate) fn tls<E>(source: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Error::Tls(source.into())
+    }
+
+    /// The SQLSTATE reported by the server, if any.
+    pub fn sql_state(&self) -> Option<&SqlState> {
+        match self {
+            Error::UniqueViolation { source, .. }
+            | Error::ForeignKeyViolation { source, .. }
+            | Error::CheckViolation { source, .. }
+            | Error::NotNullViolation { source, .. }
+            | Error::SerializationFailure(source)
+            | Error::DeadlockDetected(source)
+            | Error::Postgres(source) => source.code(),
+            _ => None,
+        }
+    }
+
+    /// Whether re-running the transaction may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::SerializationFailure(_) | Error::DeadlockDetected(_) | Error::PoolTimeout
+        )
+    }
+
+    /// A stable, machine readable code, set as the `code` extension of GraphQL errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound => "NOT_FOUND",
+            Error::UniqueViolation { .. } => "UNIQUE_VIOLATION",
+            Error::ForeignKeyViolation { .. } => "FOREIGN_KEY_VIOLATION",
+            Error::CheckViolation { .. } => "CHECK_VIOLATION",
+            Error::NotNullViolation { .. } => "NOT_NULL_VIOLATION",
+            Error::SerializationFailure(_) => "SERIALIZATION_FAILURE",
+            Error::DeadlockDetected(_) => "DEADLOCK_DETECTED",
+            Error::PoolTimeout => "POOL_TIMEOUT",
+            Error::Decode { .. } => "DECODE_ERROR",
+            Error::UnknownColumn { .. } => "UNKNOWN_COLUMN",
+            Error::ColumnCount { .. } => "COLUMN_COUNT",
+            Error::Cursor(_) => "INVALID_CURSOR",
+            Error::Tls(_) => "TLS_ERROR",
+            Error::Config(_) => "CONFIG_ERROR",
+            Error::InvalidQuery(_) => "INVALID_QUERY",
+            Error::Postgres(_) => "DATABASE_ERROR",
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(e: tokio_postgres::Error) -> Self {
+        let Some(db) = e.as_db_error() else {
+            return Error::Postgres(e);
+        };
+
+        let constraint = db.constraint().unwrap_or_default().to_string();
+        match *db.code() {
+            SqlState::UNIQUE_VIOLATION => Error::UniqueViolation {
+                constraint,
+                source: e,
+            },
+            SqlState::FOREIGN_KEY_VIOLATION => Error::ForeignKeyViolation {
+                constraint,
+                source: e,
+            },
+            SqlState::CHECK_VIOLATION => Error::CheckViolation {
+                constraint,
+                source: e,
+            },
+            SqlState::NOT_NULL_VIOLATION => Error::NotNullViolation {
+                column: db.column().unwrap_or_default().to_string(),
+                source: e,
+            },
+            SqlState::T_R_SERIALIZATION_FAILURE => Error::SerializationFailure(e),
+            SqlState::T_R_DEADLOCK_DETECTED => Error::DeadlockDetected(e),
+            _ => Error::Postgres(e),
+        }
+    }
+}
+
+impl From<RunError<tokio_postgres::Error>> for Error {
+    fn from(e: RunError<tokio_postgres::Error>) -> Self {
+        match e {
+            RunError::User(e) => e.into(),
+            RunError::TimedOut => Error::PoolTimeout,
+        }
+    }
+}
+
+/// Keeps the error code, which converting through `Display` drops.
+#[cfg(feature = "graphql")]
+impl ErrorExtensions for Error {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", self.code());
+            match self {
+                Error::UniqueViolation { constraint, .. }
+                | Error::ForeignKeyViolation { constraint, .. }
+                | Error::CheckViolation { constraint, .. } => e.set("constraint", constraint),
+                Error::NotNullViolation { column, .. }
+                | Error::Decode { column, .. }
+                | Error::UnknownColumn { column, .. } => e.set("column", column),
+                _ => {}
+            }
+        })
+    }
+}
@@ -0,0 +1,268 @@
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use async_trait::async_trait;
+use futures_util::{stream, Stream};
+use tokio_postgres::{Client, Error, Row};
+
+use crate::copy::copy_in;
+use crate::error::Result;
+use crate::insert::{insert, insert_many};
+use crate::key::{delete_by_id, find, find_many, save, update_by_id};
+use crate::model::{Model, PrimaryKey, UpdateModel};
+use crate::pagination::{Cursor, CursorExt};
+use crate::pool::*;
+use crate::types::ToSql;
+use crate::upsert::UpsertClause;
+use crate::where_select::WhereSelectClause;
+use crate::where_update::WhereUpdateClause;
+
+/// A client kept checked out until the guard drops.
+pub type ClientGuard<'a> = Box<dyn Deref<Target = Client> + Send + Sync + 'a>;
+
+/// Something queries can be run on: a pool, a pinned connection, a transaction or a database.
+#[async_trait]
+pub trait Executor: Send + Sync {
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>>;
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>>;
+
+    /// Fails with [`crate::Error::NotFound`] when the query returns no rows.
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row>;
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64>;
+
+    /// A client for work spanning several round-trips on one session, such as `COPY`.
+    async fn checkout(&self) -> Result<ClientGuard<'_>>;
+
+    /// Whether queries already run inside a transaction.
+    fn in_transaction(&self) -> bool {
+        false
+    }
+
+    fn bind<T>(&self) -> WhereSelectClause<T, Self>
+    where
+        T: Model + CursorExt<Cursor> + Sync + Send,
+        Self: Sized,
+    {
+        WhereSelectClause {
+            executor: self,
+            is_delete: false,
+            params: vec![],
+            ops: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    async fn insert<T>(&self, data: &T) -> Result<T>
+    where
+        T: Model + Sync,
+        Self: Sized,
+    {
+        insert(self, data).await
+    }
+
+    async fn insert_many<T>(&self, data: &[T]) -> Result<Vec<T>>
+    where
+        T: Model + Sync,
+        Self: Sized,
+    {
+        insert_many(self, data).await
+    }
+
+    /// Inserts `data` with a binary `COPY`, returning the number of rows copied.
+    async fn copy_in<T>(&self, data: &[T]) -> Result<u64>
+    where
+        T: Model + Sync,
+        Self: Sized,
+    {
+        copy_in::<_, T, _, _>(self, stream::iter(data)).await
+    }
+
+    /// Like [`Executor::copy_in`], writing rows as `rows` yields them.
+    async fn copy_in_stream<T, S>(&self, rows: S) -> Result<u64>
+    where
+        T: Model + Send + Sync,
+        S: Stream<Item = T> + Send,
+        Self: Sized,
+    {
+        copy_in::<_, T, _, _>(self, rows).await
+    }
+
+    fn upsert<'a, T>(&'a self, data: &'a T) -> UpsertClause<'a, T, Self>
+    where
+        T: Model,
+        Self: Sized,
+    {
+        self.upsert_many(std::slice::from_ref(data))
+    }
+
+    fn upsert_many<'a, T>(&'a self, data: &'a [T]) -> UpsertClause<'a, T, Self>
+    where
+        T: Model,
+        Self: Sized,
+    {
+        UpsertClause {
+            executor: self,
+            data,
+            target: None,
+            excluded: vec![],
+            columns: vec![],
+            params: vec![],
+        }
+    }
+
+    fn update<'a, T, U>(&'a self, data: &'a U) -> WhereUpdateClause<'a, T, U, Self>
+    where
+        T: Model,
+        U: UpdateModel,
+        Self: Sized,
+    {
+        WhereUpdateClause {
+            executor: self,
+            columns: data.columns(),
+            params: data.params(),
+            ops: vec![],
+            _t: PhantomData,
+            _u: PhantomData,
+        }
+    }
+
+    fn delete<T>(&self) -> WhereSelectClause<T, Self>
+    where
+        T: Model,
+        Self: Sized,
+    {
+        WhereSelectClause {
+            executor: self,
+            is_delete: true,
+            params: vec![],
+            ops: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    async fn find<T>(&self, id: &T::Key) -> Result<Option<T>>
+    where
+        T: PrimaryKey,
+        Self: Sized,
+    {
+        find(self, id).await
+    }
+
+    async fn find_many<T>(&self, ids: &[T::Key]) -> Result<Vec<T>>
+    where
+        T: PrimaryKey,
+        Self: Sized,
+    {
+        find_many(self, ids).await
+    }
+
+    async fn update_by_id<T, U>(&self, id: &T::Key, data: &U) -> Result<Option<T>>
+    where
+        T: PrimaryKey,
+        U: UpdateModel + Sync,
+        Self: Sized,
+    {
+        update_by_id(self, id, data).await
+    }
+
+    async fn delete_by_id<T>(&self, id: &T::Key) -> Result<Option<T>>
+    where
+        T: PrimaryKey,
+        Self: Sized,
+    {
+        delete_by_id(self, id).await
+    }
+
+    /// Updates the row with `data`'s primary key, or fails with [`crate::Error::NotFound`].
+    async fn save<T>(&self, data: &T) -> Result<T>
+    where
+        T: PrimaryKey + Sync,
+        Self: Sized,
+    {
+        save(self, data).await
+    }
+}
+
+#[async_trait]
+impl Executor for Client {
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>> {
+        Ok(Client::query(self, query, params).await?)
+    }
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>> {
+        Ok(Client::query_opt(self, query, params).await?)
+    }
+
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row> {
+        Client::query_opt(self, query, params)
+            .await?
+            .ok_or(crate::Error::NotFound)
+    }
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64> {
+        Ok(Client::execute(self, query, params).await?)
+    }
+
+    async fn checkout(&self) -> Result<ClientGuard<'_>> {
+        Ok(Box::new(self))
+    }
+}
+
+#[async_trait]
+impl<M> Executor for PooledConnection<'_, M>
+where
+    M: ManageConnection<Connection = Client, Error = Error>,
+{
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>> {
+        Executor::query(&**self, query, params).await
+    }
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>> {
+        Executor::query_opt(&**self, query, params).await
+    }
+
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row> {
+        Executor::query_one(&**self, query, params).await
+    }
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64> {
+        Executor::execute(&**self, query, params).await
+    }
+
+    async fn checkout(&self) -> Result<ClientGuard<'_>> {
+        Ok(Box::new(&**self))
+    }
+}
+
+/// Each query checks out its own connection.
+#[async_trait]
+impl<M> Executor for Pool<M>
+where
+    M: ManageConnection<Connection = Client, Error = Error>,
+{
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>> {
+        let conn = self.get().await?;
+        Executor::query(&conn, query, params).await
+    }
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>> {
+        let conn = self.get().await?;
+        Executor::query_opt(&conn, query, params).await
+    }
+
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row> {
+        let conn = self.get().await?;
+        Executor::query_one(&conn, query, params).await
+    }
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64> {
+        let conn = self.get().await?;
+        Executor::execute(&conn, query, params).await
+    }
+
+    async fn checkout(&self) -> Result<ClientGuard<'_>> {
+        Ok(Box::new(self.get().await?))
+    }
+}
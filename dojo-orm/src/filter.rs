@@ -0,0 +1,321 @@
+use std::ops::Not;
+
+use crate::column::{ColumnOf, IntoColumn, SqlArray};
+use crate::ops::{Op, OpValue, OpValueType};
+use crate::types::ToSql;
+
+pub type BoxedParam = Box<dyn ToSql + Sync + Send>;
+
+#[derive(Debug)]
+pub struct FilterValue {
+    pub ty: OpValueType,
+    pub column: String,
+    pub op: &'static str,
+    pub values: Vec<BoxedParam>,
+}
+
+/// An owned filter tree. The default is an empty `AND`, matching every row.
+#[derive(Debug)]
+pub enum Filter {
+    Value(FilterValue),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::And(vec![])
+    }
+}
+
+impl Filter {
+    fn value<'c>(
+        ty: OpValueType,
+        column: impl IntoColumn<'c>,
+        op: &'static str,
+        values: Vec<BoxedParam>,
+    ) -> Self {
+        Filter::Value(FilterValue {
+            ty,
+            column: column.into_column().into_owned(),
+            op,
+            values,
+        })
+    }
+
+    pub fn and(filters: impl IntoIterator<Item = Filter>) -> Self {
+        Filter::And(filters.into_iter().collect())
+    }
+
+    pub fn or(filters: impl IntoIterator<Item = Filter>) -> Self {
+        Filter::Or(filters.into_iter().collect())
+    }
+
+    pub fn eq<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, "=", vec![Box::new(value)])
+    }
+
+    pub fn ne<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, "<>", vec![Box::new(value)])
+    }
+
+    pub fn gt<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, ">", vec![Box::new(value)])
+    }
+
+    pub fn gte<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, ">=", vec![Box::new(value)])
+    }
+
+    pub fn lt<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, "<", vec![Box::new(value)])
+    }
+
+    pub fn lte<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, "<=", vec![Box::new(value)])
+    }
+
+    pub fn between<'c, C, T>(column: C, low: T, high: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(
+            OpValueType::Range,
+            column,
+            "BETWEEN",
+            vec![Box::new(low), Box::new(high)],
+        )
+    }
+
+    pub fn like<'c, C, T>(column: C, pattern: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, "LIKE", vec![Box::new(pattern)])
+    }
+
+    pub fn ilike<'c, C, T>(column: C, pattern: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Value, column, "ILIKE", vec![Box::new(pattern)])
+    }
+
+    pub fn eq_ignore_case<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(
+            OpValueType::CaseInsensitive,
+            column,
+            "=",
+            vec![Box::new(value)],
+        )
+    }
+
+    pub fn in_list<'c, C, T>(column: C, values: T) -> Self
+    where
+        C: ColumnOf<'c, T::Item>,
+        T: ToSql + SqlArray + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::Array, column, "=", vec![Box::new(values)])
+    }
+
+    pub fn not_in<'c, C, T>(column: C, values: T) -> Self
+    where
+        C: ColumnOf<'c, T::Item>,
+        T: ToSql + SqlArray + Sync + Send + 'static,
+    {
+        Self::value(OpValueType::ArrayAll, column, "<>", vec![Box::new(values)])
+    }
+
+    pub fn is_null<'c>(column: impl IntoColumn<'c>) -> Self {
+        Self::value(OpValueType::Unary, column, "IS NULL", vec![])
+    }
+
+    pub fn is_not_null<'c>(column: impl IntoColumn<'c>) -> Self {
+        Self::value(OpValueType::Unary, column, "IS NOT NULL", vec![])
+    }
+
+    pub fn is_distinct_from<'c, C, T>(column: C, value: T) -> Self
+    where
+        C: ColumnOf<'c, T>,
+        T: ToSql + Sync + Send + 'static,
+    {
+        Self::value(
+            OpValueType::Value,
+            column,
+            "IS DISTINCT FROM",
+            vec![Box::new(value)],
+        )
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Filter::And(filters) | Filter::Or(filters) if filters.is_empty())
+    }
+
+    /// Combines with `other` using `AND`, appending to `self` when it already is one.
+    pub fn and_also(self, other: Filter) -> Self {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return self;
+        }
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    /// Combines with `other` using `OR`, appending to `self` when it already is one.
+    pub fn or_else(self, other: Filter) -> Self {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return self;
+        }
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    /// Like [`Filter::and_also`], but leaves `self` untouched when `other` is `None`.
+    pub fn and_also_opt(self, other: Option<Filter>) -> Self {
+        match other {
+            Some(other) => self.and_also(other),
+            None => self,
+        }
+    }
+
+    /// Like [`Filter::or_else`], but leaves `self` untouched when `other` is `None`.
+    pub fn or_else_opt(self, other: Option<Filter>) -> Self {
+        match other {
+            Some(other) => self.or_else(other),
+            None => self,
+        }
+    }
+
+    pub fn to_op(&self) -> Op<'_> {
+        match self {
+            Filter::Value(value) => Op::Value(OpValue {
+                ty: value.ty,
+                column: value.column.as_str().into(),
+                op: value.op,
+                values: value
+                    .values
+                    .iter()
+                    .map(|v| &**v as &(dyn ToSql + Sync))
+                    .collect(),
+            }),
+            Filter::And(filters) => Op::And(filters.iter().map(Filter::to_op).collect()),
+            Filter::Or(filters) => Op::Or(filters.iter().map(Filter::to_op).collect()),
+            Filter::Not(filter) => Op::Not(Box::new(filter.to_op())),
+        }
+    }
+}
+
+impl Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Filter::Not(filter) => *filter,
+            filter => Filter::Not(Box::new(filter)),
+        }
+    }
+}
+
+impl<'a> From<&'a Filter> for Op<'a> {
+    fn from(filter: &'a Filter) -> Self {
+        filter.to_op()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let name: Option<String> = None;
+        let filter = Filter::default()
+            .and_also(Filter::eq("role", "admin".to_string()))
+            .and_also_opt(name.map(|name| Filter::eq("name", name)))
+            .and_also_opt(Some(Filter::between("age", 18, 30)))
+            .and_also(Filter::is_null("deleted_at").or_else(!Filter::gt("score", 10)));
+
+        let mut params_index = 1;
+        let op = filter.to_op();
+        let (query, params) = op.sql(&mut params_index);
+        assert_eq!(
+            query,
+            r#"("role" = $1 AND "age" BETWEEN $2 AND $3 AND ("deleted_at" IS NULL OR NOT ("score" > $4)))"#
+        );
+        assert_eq!(params.len(), 4);
+
+        let filter = Filter::default();
+        let op = filter.to_op();
+        let (query, params) = op.sql(&mut 1);
+        assert_eq!(query, "TRUE");
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn test_filter_from_default() {
+        let filter = ["a", "b"]
+            .into_iter()
+            .fold(Filter::default(), |filter, name| {
+                filter.or_else(Filter::eq("name", name.to_string()))
+            });
+        let op = filter.to_op();
+        let (query, params) = op.sql(&mut 1);
+        assert_eq!(query, r#"("name" = $1 OR "name" = $2)"#);
+        assert_eq!(params.len(), 2);
+
+        let filter = Filter::or([]).and_also(Filter::eq("role", "admin".to_string()));
+        let (query, _) = filter.to_op().sql(&mut 1);
+        assert_eq!(query, r#""role" = $1"#);
+
+        let filter = Filter::is_null("deleted_at").or_else(Filter::default());
+        let (query, _) = filter.to_op().sql(&mut 1);
+        assert_eq!(query, r#""deleted_at" IS NULL"#);
+    }
+}
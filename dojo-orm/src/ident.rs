@@ -0,0 +1,36 @@
+use crate::error::{Error, Result};
+use crate::model::Model;
+
+/// Quotes an identifier, doubling any embedded `"`.
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// The quoted, schema-qualified table name of `T`.
+pub(crate) fn table<T: Model>() -> String {
+    match T::SCHEMA {
+        Some(schema) => format!("{}.{}", quote_ident(schema), quote_ident(T::NAME)),
+        None => quote_ident(T::NAME),
+    }
+}
+
+/// The quoted columns of `T`, comma separated.
+pub(crate) fn columns<T: Model>() -> String {
+    T::COLUMNS
+        .iter()
+        .map(|c| quote_ident(c))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Rejects column names `T` does not declare.
+pub(crate) fn check_column<T: Model>(column: &str) -> Result<()> {
+    if T::COLUMNS.contains(&column) {
+        Ok(())
+    } else {
+        Err(Error::UnknownColumn {
+            table: T::NAME,
+            column: column.to_string(),
+        })
+    }
+}
@@ -0,0 +1,95 @@
+use tokio_postgres::Row;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::ident::{columns, table};
+use crate::model::Model;
+use crate::transaction::Atomic;
+use crate::types::ToSql;
+
+/// The most bind parameters Postgres accepts in one statement.
+pub(crate) const MAX_PARAMS: usize = u16::MAX as usize;
+
+/// Renders the `INSERT ... VALUES` statement for `data`, without a `RETURNING`.
+pub(crate) fn insert_sql<'a, T: Model>(
+    data: &'a [T],
+    params_index: &mut usize,
+    params: &mut Vec<&'a (dyn ToSql + Sync)>,
+) -> String {
+    let mut query = "INSERT INTO ".to_string();
+    query.push_str(&table::<T>());
+    query.push_str(" (");
+    query.push_str(&columns::<T>());
+    query.push_str(") VALUES ");
+
+    let mut list_values = vec![];
+    for item in data {
+        let mut values = vec![];
+        for param in item.params() {
+            values.push(format!("${}", params_index));
+            params.push(param);
+            *params_index += 1;
+        }
+        list_values.push(format!("({})", values.join(", ")));
+    }
+    query.push_str(list_values.join(", ").as_str());
+
+    query
+}
+
+pub(crate) async fn insert<E, T>(executor: &E, data: &T) -> Result<T>
+where
+    E: Executor,
+    T: Model,
+{
+    let mut params = vec![];
+    let mut query = insert_sql(std::slice::from_ref(data), &mut 1, &mut params);
+    query.push_str(" RETURNING ");
+    query.push_str(&columns::<T>());
+
+    debug!("query: {}, params: {:?}", query, params);
+    let row = executor.query_one(query.as_str(), &params).await?;
+
+    T::from_row(row)
+}
+
+/// Inserts `data` in as few statements as the bind parameter limit allows, all or none.
+pub(crate) async fn insert_many<E, T>(executor: &E, data: &[T]) -> Result<Vec<T>>
+where
+    E: Executor,
+    T: Model,
+{
+    if data.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let chunk_size = (MAX_PARAMS / T::COLUMNS.len().max(1)).max(1);
+    if data.len() <= chunk_size {
+        let rows = insert_chunk(executor, data).await?;
+        return rows.into_iter().map(T::from_row).collect();
+    }
+
+    let atomic = Atomic::begin(executor).await?;
+    let mut inserted = vec![];
+    for chunk in data.chunks(chunk_size) {
+        inserted.extend(insert_chunk(atomic.client(), chunk).await?);
+    }
+    atomic.commit().await?;
+
+    inserted.into_iter().map(T::from_row).collect()
+}
+
+async fn insert_chunk<E, T>(executor: &E, chunk: &[T]) -> Result<Vec<Row>>
+where
+    E: Executor + ?Sized,
+    T: Model,
+{
+    let mut params = vec![];
+    let mut query = insert_sql(chunk, &mut 1, &mut params);
+    query.push_str(" RETURNING ");
+    query.push_str(&columns::<T>());
+
+    debug!("query: {}, params: {:?}", query, params);
+    executor.query(query.as_str(), &params).await
+}
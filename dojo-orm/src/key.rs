@@ -0,0 +1,163 @@
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{check_column, columns, quote_ident, table};
+use crate::model::{PrimaryKey, UpdateModel};
+use crate::ops::row_eq;
+use crate::types::ToSql;
+
+/// `"id" = $n`, or `("team_id", "user_id") = ($n, $n+1)` for a composite key.
+fn key_condition<T: PrimaryKey>(params: &[&(dyn ToSql + Sync)], params_index: usize) -> String {
+    let (query, _) = row_eq(T::PRIMARY_KEY, params).sql(&mut { params_index });
+    query
+}
+
+pub(crate) async fn find<E, T>(executor: &E, id: &T::Key) -> Result<Option<T>>
+where
+    E: Executor,
+    T: PrimaryKey,
+{
+    let params = T::key_params(id);
+    let query = format!(
+        "SELECT {} FROM {} WHERE {}",
+        columns::<T>(),
+        table::<T>(),
+        key_condition::<T>(&params, 1)
+    );
+
+    debug!("query: {}, params: {:?}", query, params);
+    executor
+        .query_opt(query.as_str(), &params)
+        .await?
+        .map(T::from_row)
+        .transpose()
+}
+
+pub(crate) async fn find_many<E, T>(executor: &E, ids: &[T::Key]) -> Result<Vec<T>>
+where
+    E: Executor,
+    T: PrimaryKey,
+{
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let op = T::keys_in(ids);
+    let (condition, params) = op.sql(&mut 1);
+    let query = format!(
+        "SELECT {} FROM {} WHERE {}",
+        columns::<T>(),
+        table::<T>(),
+        condition
+    );
+
+    debug!("query: {}, params: {:?}", query, params);
+    let rows = executor.query(query.as_str(), &params).await?;
+
+    rows.into_iter().map(T::from_row).collect()
+}
+
+pub(crate) async fn update_by_id<E, T, U>(executor: &E, id: &T::Key, data: &U) -> Result<Option<T>>
+where
+    E: Executor,
+    T: PrimaryKey,
+    U: UpdateModel,
+{
+    let update_columns = data.columns();
+    if update_columns.is_empty() {
+        return find(executor, id).await;
+    }
+
+    let mut params = data.params();
+    let mut sets = vec![];
+    for (i, column) in update_columns.iter().enumerate() {
+        check_column::<T>(column)?;
+        sets.push(format!("{} = ${}", quote_ident(column), i + 1));
+    }
+    let key_params = T::key_params(id);
+    let condition = key_condition::<T>(&key_params, params.len() + 1);
+    params.extend(key_params);
+
+    let query = format!(
+        "UPDATE {} SET {} WHERE {} RETURNING {}",
+        table::<T>(),
+        sets.join(", "),
+        condition,
+        columns::<T>()
+    );
+
+    debug!("query: {}, params: {:?}", query, params);
+    executor
+        .query_opt(query.as_str(), &params)
+        .await?
+        .map(T::from_row)
+        .transpose()
+}
+
+pub(crate) async fn delete_by_id<E, T>(executor: &E, id: &T::Key) -> Result<Option<T>>
+where
+    E: Executor,
+    T: PrimaryKey,
+{
+    let params = T::key_params(id);
+    let query = format!(
+        "DELETE FROM {} WHERE {} RETURNING {}",
+        table::<T>(),
+        key_condition::<T>(&params, 1),
+        columns::<T>()
+    );
+
+    debug!("query: {}, params: {:?}", query, params);
+    executor
+        .query_opt(query.as_str(), &params)
+        .await?
+        .map(T::from_row)
+        .transpose()
+}
+
+/// Writes every column of `data` back to the row with the same primary key.
+pub(crate) async fn save<E, T>(executor: &E, data: &T) -> Result<T>
+where
+    E: Executor,
+    T: PrimaryKey,
+{
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+    let mut sets = vec![];
+    for (column, param) in T::COLUMNS.iter().zip(data.params()) {
+        if T::PRIMARY_KEY.contains(column) {
+            continue;
+        }
+        params.push(param);
+        sets.push(format!("{} = ${}", quote_ident(column), params.len()));
+    }
+    let key_params = data.primary_key_params();
+    let condition = key_condition::<T>(&key_params, params.len() + 1);
+    params.extend(key_params);
+
+    // a model made only of its key has nothing to write, but must still exist
+    let query = if sets.is_empty() {
+        format!(
+            "SELECT {} FROM {} WHERE {}",
+            columns::<T>(),
+            table::<T>(),
+            condition
+        )
+    } else {
+        format!(
+            "UPDATE {} SET {} WHERE {} RETURNING {}",
+            table::<T>(),
+            sets.join(", "),
+            condition,
+            columns::<T>()
+        )
+    };
+
+    debug!("query: {}, params: {:?}", query, params);
+    let row = executor
+        .query_opt(query.as_str(), &params)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    T::from_row(row)
+}
@@ -1,10 +1,24 @@
+mod batch;
+mod column;
+mod copy;
 mod cursor;
 mod database;
+mod error;
+mod executor;
+mod filter;
+mod ident;
+mod insert;
+mod key;
 mod limit;
 mod model;
 pub mod ops;
 mod order_by;
 pub mod pagination;
+mod select;
+mod stream;
+pub mod tls;
+mod transaction;
+mod upsert;
 mod where_select;
 mod where_update;
 
@@ -13,6 +27,7 @@ pub mod types {
 }
 
 pub mod pool {
+    pub use bb8::ManageConnection;
     pub use bb8::Pool;
     pub use bb8::PooledConnection;
     pub use bb8_postgres::PostgresConnectionManager;
@@ -23,5 +38,12 @@ pub mod bytes {
     pub use bytes::*;
 }
 
+pub use column::*;
 pub use database::*;
+pub use error::*;
+pub use executor::*;
+pub use filter::*;
+pub use ident::quote_ident;
 pub use model::*;
+pub use order_by::{Nulls, Order, OrderBy};
+pub use transaction::*;
@@ -1,21 +1,110 @@
-use std::collections::HashMap;
 use std::marker::PhantomData;
 
+use futures_util::Stream;
+use tracing::debug;
+
 use crate::types::ToSql;
 
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{columns, table};
 use crate::model::Model;
-use crate::ops::Op;
-use crate::order_by::Order;
+use crate::ops::{where_sql, Op};
+use crate::order_by::{order_by_sql, OrderBy};
+use crate::stream::query_stream;
+
+/// Renders ` LIMIT n OFFSET m` for whichever is set, rejecting negative values.
+pub(crate) fn limit_sql(limit: Option<i64>, offset: Option<i64>) -> Result<String> {
+    let mut sql = String::new();
+    for (keyword, value) in [("LIMIT", limit), ("OFFSET", offset)] {
+        let Some(value) = value else {
+            continue;
+        };
+        if value < 0 {
+            return Err(Error::InvalidQuery(format!(
+                "{} must not be negative, got {}",
+                keyword, value
+            )));
+        }
+        sql.push_str(&format!(" {} {}", keyword, value));
+    }
 
-pub struct LimitClause<'a, T>
+    Ok(sql)
+}
+
+pub struct LimitClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
+    pub(crate) executor: &'a E,
     pub(crate) params: &'a [&'a (dyn ToSql + Sync)],
     pub(crate) ops: &'a [Op<'a>],
-    pub(crate) orders: Option<&'a HashMap<&'a str, Order>>,
-    pub(crate) limit: i32,
+    pub(crate) orders: &'a [OrderBy<'a>],
+    pub(crate) limit: Option<i64>,
+    pub(crate) offset: Option<i64>,
     pub(crate) _t: PhantomData<T>,
 }
 
-impl<'a, T> LimitClause<'a, T> where T: Model {}
+impl<'a, T, E> LimitClause<'a, T, E>
+where
+    T: Model,
+    E: Executor,
+{
+    /// Skips the first `offset` rows.
+    pub fn offset(&'a mut self, offset: i64) -> &'a mut Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    fn build_with_limit(
+        &'a self,
+        limit: Option<i64>,
+    ) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        let mut params_index = 1;
+
+        let mut params = self.params.to_vec();
+        let mut query = "SELECT ".to_string();
+        query.push_str(&columns::<T>());
+        query.push_str(" FROM ");
+        query.push_str(&table::<T>());
+
+        query.push_str(&where_sql::<T>(self.ops, &mut params_index, &mut params)?);
+
+        query.push_str(&order_by_sql::<T>(self.orders)?);
+
+        query.push_str(&limit_sql(limit, self.offset)?);
+
+        Ok((query, params))
+    }
+
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        self.build_with_limit(self.limit)
+    }
+
+    pub async fn first(&'a self) -> Result<Option<T>> {
+        let (query, params) = self.build_with_limit(Some(1))?;
+        debug!("query: {}, params: {:?}", query, params);
+        self.executor
+            .query_opt(query.as_str(), &params)
+            .await?
+            .map(T::from_row)
+            .transpose()
+    }
+
+    /// Streams the rows instead of collecting them. See `WhereSelectClause::stream`.
+    pub fn stream(&'a self) -> impl Stream<Item = Result<T>> + 'a {
+        query_stream(self.executor, self.build())
+    }
+
+    pub async fn all(&'a self) -> Result<Vec<T>> {
+        let (query, params) = self.build()?;
+        debug!("query: {}, params: {:?}", query, params);
+        let mut rows = vec![];
+        for row in self.executor.query(query.as_str(), &params).await? {
+            rows.push(T::from_row(row)?);
+        }
+
+        Ok(rows)
+    }
+}
@@ -1,10 +1,15 @@
+use std::fmt::Debug;
+
+use crate::error::Result;
+use crate::ops::Op;
 use crate::types::ToSql;
 
 pub trait Model {
     const NAME: &'static str;
+    const SCHEMA: Option<&'static str> = None;
     const COLUMNS: &'static [&'static str];
     fn params(&self) -> Vec<&(dyn ToSql + Sync)>;
-    fn from_row(row: tokio_postgres::Row) -> anyhow::Result<Self>
+    fn from_row(row: tokio_postgres::Row) -> Result<Self>
     where
         Self: Sized;
 }
@@ -14,3 +19,23 @@ pub trait UpdateModel {
     fn columns(&self) -> Vec<&'static str>;
     fn params(&self) -> Vec<&(dyn ToSql + Sync)>;
 }
+
+/// Generated by `#[dojo(primary_key)]`, with a tuple `Key` for composite keys.
+pub trait PrimaryKey: Model {
+    const PRIMARY_KEY: &'static [&'static str];
+    type Key: Debug + Sync + Send;
+    fn key_params(key: &Self::Key) -> Vec<&(dyn ToSql + Sync)>;
+    fn primary_key_params(&self) -> Vec<&(dyn ToSql + Sync)>;
+    fn primary_key(&self) -> Self::Key;
+    /// Matches the rows keyed by any of `ids`, binding one array per key column.
+    fn keys_in(ids: &[Self::Key]) -> Op<'static>;
+}
+
+/// A row holding some of a model's columns, read with `.select::<P>()`.
+pub trait Projection {
+    /// Each field's name, with the expression it's computed from when it isn't a column.
+    const SELECT: &'static [(&'static str, Option<&'static str>)];
+    fn from_row(row: tokio_postgres::Row) -> Result<Self>
+    where
+        Self: Sized;
+}
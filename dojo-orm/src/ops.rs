@@ -1,163 +1,548 @@
 use std::borrow::Cow;
+use std::sync::Arc;
 
-use crate::order_by::Order;
+use crate::column::{ColumnOf, IntoColumn, SharedParam, SqlArray, SqlTuple};
+use crate::error::{Error, Result};
+use crate::ident::{check_column, quote_ident};
+use crate::model::Model;
+use crate::order_by::{Order, OrderBy};
 use crate::types::ToSql;
 
-#[derive(Debug)]
+/// How an [`OpValue`] is rendered, and how many values it binds.
+#[derive(Debug, Clone, Copy)]
 pub enum OpValueType {
+    /// `column op $1`
     Value,
+    /// `column op ANY($1)`
     Array,
+    /// `column op ALL($1)`
+    ArrayAll,
+    /// `column op $1 AND $2`
+    Range,
+    /// `column op`, binding nothing.
+    Unary,
+    /// `LOWER(column) op LOWER($1)`
+    CaseInsensitive,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OpValue<'a> {
     pub ty: OpValueType,
     pub column: Cow<'a, str>,
     pub op: &'a str,
-    pub value: &'a (dyn ToSql + Sync),
+    pub values: Vec<&'a (dyn ToSql + Sync)>,
 }
 
-#[derive(Debug)]
+/// A row-value comparison: `(a, b) op ($1, $2)`.
+#[derive(Debug, Clone)]
+pub struct OpRow<'a> {
+    pub columns: Vec<Cow<'a, str>>,
+    pub op: &'a str,
+    pub values: Vec<&'a (dyn ToSql + Sync)>,
+}
+
+/// `column = ANY($1)` over an array the op owns, as built by `in_array`.
+#[derive(Debug, Clone)]
+pub struct OpArray<'a> {
+    pub column: Cow<'a, str>,
+    pub values: SharedParam,
+}
+
+/// A row-value membership test against arrays unnested into rows.
+#[derive(Debug, Clone)]
+pub struct OpTuples<'a> {
+    pub columns: Vec<Cow<'a, str>>,
+    pub arrays: Vec<(&'static str, SharedParam)>,
+}
+
+#[derive(Debug, Clone)]
 pub enum Op<'a> {
     Value(OpValue<'a>),
-    And(&'a [Op<'a>]),
-    Or(&'a [Op<'a>]),
+    Row(OpRow<'a>),
+    Array(OpArray<'a>),
+    Tuples(OpTuples<'a>),
+    And(Cow<'a, [Op<'a>]>),
+    Or(Cow<'a, [Op<'a>]>),
+    Not(Box<Op<'a>>),
+}
+
+/// Checks a row value names one declared column of `T` per value.
+fn check_row<T: Model>(columns: &[Cow<str>], values: usize) -> Result<()> {
+    if columns.len() != values {
+        return Err(Error::ColumnCount {
+            columns: columns.len(),
+            values,
+        });
+    }
+    columns
+        .iter()
+        .try_for_each(|column| check_column::<T>(column))
 }
 
 impl<'a> Op<'a> {
-    pub fn sql(&self, params_index: &mut usize) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    /// Checks every column the op refers to is declared by `T`.
+    pub(crate) fn validate<T: Model>(&self) -> Result<()> {
+        match self {
+            Op::Value(op_value) => check_column::<T>(&op_value.column),
+            Op::Array(op_array) => check_column::<T>(&op_array.column),
+            Op::Row(OpRow {
+                columns, values, ..
+            }) => check_row::<T>(columns, values.len()),
+            Op::Tuples(OpTuples { columns, arrays }) => check_row::<T>(columns, arrays.len()),
+            Op::And(ops) | Op::Or(ops) => ops.iter().try_for_each(Op::validate::<T>),
+            Op::Not(op) => op.validate::<T>(),
+        }
+    }
+
+    pub fn sql(&self, params_index: &mut usize) -> (String, Vec<&(dyn ToSql + Sync)>) {
         match self {
             Op::Value(op_value) => {
+                let (column, op) = (quote_ident(&op_value.column), op_value.op);
                 let query = match op_value.ty {
-                    OpValueType::Value => {
-                        format!("{} {} ${}", op_value.column, op_value.op, params_index)
-                    }
-                    OpValueType::Array => {
-                        format!("{} {} ANY(${})", op_value.column, op_value.op, params_index)
+                    OpValueType::Value => format!("{} {} ${}", column, op, params_index),
+                    OpValueType::Array => format!("{} {} ANY(${})", column, op, params_index),
+                    OpValueType::ArrayAll => format!("{} {} ALL(${})", column, op, params_index),
+                    OpValueType::Range => format!(
+                        "{} {} ${} AND ${}",
+                        column,
+                        op,
+                        params_index,
+                        *params_index + 1
+                    ),
+                    OpValueType::Unary => format!("{} {}", column, op),
+                    OpValueType::CaseInsensitive => {
+                        format!("LOWER({}) {} LOWER(${})", column, op, params_index)
                     }
                 };
-                let params = vec![op_value.value];
+                let params = op_value.values.clone();
+                *params_index += params.len();
+                (query, params)
+            }
+            Op::Row(op_row) => {
+                let mut columns = vec![];
+                let mut values = vec![];
+                for column in &op_row.columns {
+                    columns.push(quote_ident(column));
+                    values.push(format!("${}", params_index));
+                    *params_index += 1;
+                }
+                let query = if columns.len() == 1 {
+                    format!("{} {} {}", columns[0], op_row.op, values[0])
+                } else {
+                    format!(
+                        "({}) {} ({})",
+                        columns.join(", "),
+                        op_row.op,
+                        values.join(", ")
+                    )
+                };
+                (query, op_row.values.clone())
+            }
+            Op::Array(op_array) => {
+                let query = format!("{} = ANY(${})", quote_ident(&op_array.column), params_index);
                 *params_index += 1;
+                (query, vec![op_array.values.as_ref() as &(dyn ToSql + Sync)])
+            }
+            Op::Tuples(op_tuples) => {
+                let columns = op_tuples
+                    .columns
+                    .iter()
+                    .map(|c| quote_ident(c))
+                    .collect::<Vec<_>>();
+                let mut arrays = vec![];
+                let mut params = vec![];
+                for (ty, values) in &op_tuples.arrays {
+                    arrays.push(format!("${}::{}[]", params_index, ty));
+                    params.push(values.as_ref() as &(dyn ToSql + Sync));
+                    *params_index += 1;
+                }
+                let query = format!(
+                    "({}) IN (SELECT * FROM UNNEST({}))",
+                    columns.join(", "),
+                    arrays.join(", ")
+                );
                 (query, params)
             }
             Op::And(ops) => {
                 let mut ands = vec![];
                 let mut params = vec![];
-                for op in *ops {
+                for op in ops.iter() {
                     let (q, p) = op.sql(params_index);
                     ands.push(q);
                     params.extend_from_slice(&p);
                 }
 
-                let query = format!("({})", ands.join(" AND "));
+                // an empty AND matches everything
+                let query = if ands.is_empty() {
+                    "TRUE".to_string()
+                } else {
+                    format!("({})", ands.join(" AND "))
+                };
                 (query, params)
             }
             Op::Or(ops) => {
                 let mut ors = vec![];
                 let mut params = vec![];
-                for op in *ops {
+                for op in ops.iter() {
                     let (q, p) = op.sql(params_index);
                     ors.push(q);
                     params.extend_from_slice(&p);
                 }
 
-                let query = format!("({})", ors.join(" OR "));
+                // an empty OR matches nothing
+                let query = if ors.is_empty() {
+                    "FALSE".to_string()
+                } else {
+                    format!("({})", ors.join(" OR "))
+                };
                 (query, params)
             }
+            Op::Not(op) => {
+                let (q, p) = op.sql(params_index);
+                (format!("NOT ({})", q), p)
+            }
         }
     }
 }
 
+/// Renders ` WHERE ...` joining `ops` with `AND`, or nothing when there are none.
+pub(crate) fn where_sql<'a, 'b, T: Model>(
+    ops: &'b [Op<'a>],
+    params_index: &mut usize,
+    params: &mut Vec<&'b (dyn ToSql + Sync)>,
+) -> Result<String> {
+    let mut ands = vec![];
+    for op in ops {
+        op.validate::<T>()?;
+        let (q, p) = op.sql(params_index);
+        ands.push(q);
+        params.extend_from_slice(&p);
+    }
+
+    if ands.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(format!(" WHERE {}", ands.join(" AND ")))
+    }
+}
+
 pub fn and<'a>(ops: &'a [Op<'a>]) -> Op<'a> {
-    Op::And(ops)
+    Op::And(ops.into())
 }
 
 pub fn or<'a>(ops: &'a [Op<'a>]) -> Op<'a> {
-    Op::Or(ops)
+    Op::Or(ops.into())
 }
 
-pub fn eq<'a, T: ToSql + Sync>(column: &'a str, value: &'a T) -> Op<'a> {
+pub fn not<'a>(op: Op<'a>) -> Op<'a> {
+    Op::Not(Box::new(op))
+}
+
+fn value<'a>(
+    ty: OpValueType,
+    column: impl IntoColumn<'a>,
+    op: &'a str,
+    values: Vec<&'a (dyn ToSql + Sync)>,
+) -> Op<'a> {
     Op::Value(OpValue {
-        ty: OpValueType::Value,
-        column: column.into(),
-        op: "=",
-        value,
+        ty,
+        column: column.into_column(),
+        op,
+        values,
     })
 }
 
-pub fn in_list<'a, T: ToSql + Sync>(column: &'a str, values: &'a T) -> Op<'a> {
-    Op::Value(OpValue {
-        ty: OpValueType::Array,
-        column: column.into(),
-        op: "=",
-        value: values,
+pub fn eq<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "=", vec![value])
+}
+
+pub fn ne<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "<>", vec![value])
+}
+
+pub fn gt<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, ">", vec![value])
+}
+
+pub fn gte<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, ">=", vec![value])
+}
+
+pub fn lt<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "<", vec![value])
+}
+
+pub fn lte<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "<=", vec![value])
+}
+
+/// Inclusive on both ends, like SQL `BETWEEN`.
+pub fn between<'a, C, T>(column: C, low: &'a T, high: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Range, column, "BETWEEN", vec![low, high])
+}
+
+pub fn like<'a, C, T>(column: C, pattern: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "LIKE", vec![pattern])
+}
+
+pub fn ilike<'a, C, T>(column: C, pattern: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "ILIKE", vec![pattern])
+}
+
+/// Compares `LOWER(column)` with `LOWER(value)`.
+pub fn eq_ignore_case<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::CaseInsensitive, column, "=", vec![value])
+}
+
+pub fn in_list<'a, C, T>(column: C, values: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T::Item>,
+    T: ToSql + SqlArray + Sync,
+{
+    self::value(OpValueType::Array, column, "=", vec![values])
+}
+
+pub fn not_in<'a, C, T>(column: C, values: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T::Item>,
+    T: ToSql + SqlArray + Sync,
+{
+    self::value(OpValueType::ArrayAll, column, "<>", vec![values])
+}
+
+/// Like `in_list`, but the op owns `values`, for lists built on the fly.
+pub fn in_array<'a, C, T>(column: C, values: Vec<T>) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync + Send + 'static,
+{
+    Op::Array(OpArray {
+        column: column.into_column(),
+        values: Arc::new(values),
+    })
+}
+
+/// Matches rows whose `columns` equal any of `tuples`, binding one array per column.
+pub fn in_tuples<'a, T: SqlTuple>(columns: &[&'a str], tuples: &[T]) -> Op<'a> {
+    Op::Tuples(OpTuples {
+        columns: columns.iter().map(|c| Cow::Borrowed(*c)).collect(),
+        arrays: T::unnest(tuples),
+    })
+}
+
+pub fn is_null<'a>(column: impl IntoColumn<'a>) -> Op<'a> {
+    self::value(OpValueType::Unary, column, "IS NULL", vec![])
+}
+
+pub fn is_not_null<'a>(column: impl IntoColumn<'a>) -> Op<'a> {
+    self::value(OpValueType::Unary, column, "IS NOT NULL", vec![])
+}
+
+/// Like `ne`, but treats `NULL` as a comparable value.
+pub fn is_distinct_from<'a, C, T>(column: C, value: &'a T) -> Op<'a>
+where
+    C: ColumnOf<'a, T>,
+    T: ToSql + Sync,
+{
+    self::value(OpValueType::Value, column, "IS DISTINCT FROM", vec![value])
+}
+
+/// Needs one value per column, or the query fails with [`Error::ColumnCount`].
+fn row<'a>(columns: &[&'a str], op: &'a str, values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    Op::Row(OpRow {
+        columns: columns.iter().map(|c| Cow::Borrowed(*c)).collect(),
+        op,
+        values: values.to_vec(),
     })
 }
 
-pub fn asc(column: &str) -> (&str, Order) {
-    (column, Order::Asc)
+/// `(a, b) = ($1, $2)`
+pub fn row_eq<'a>(columns: &[&'a str], values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    row(columns, "=", values)
+}
+
+pub fn row_ne<'a>(columns: &[&'a str], values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    row(columns, "<>", values)
+}
+
+/// Compares lexicographically, e.g. `(created_at, id) > ($1, $2)`.
+pub fn row_gt<'a>(columns: &[&'a str], values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    row(columns, ">", values)
+}
+
+pub fn row_gte<'a>(columns: &[&'a str], values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    row(columns, ">=", values)
 }
 
-pub fn desc(column: &str) -> (&str, Order) {
-    (column, Order::Desc)
+pub fn row_lt<'a>(columns: &[&'a str], values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    row(columns, "<", values)
+}
+
+pub fn row_lte<'a>(columns: &[&'a str], values: &[&'a (dyn ToSql + Sync)]) -> Op<'a> {
+    row(columns, "<=", values)
+}
+
+pub fn asc<'a>(column: impl Into<&'a str>) -> OrderBy<'a> {
+    OrderBy::new(column.into(), Order::Asc)
+}
+
+pub fn desc<'a>(column: impl Into<&'a str>) -> OrderBy<'a> {
+    OrderBy::new(column.into(), Order::Desc)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_and() {
-        use super::{and, eq, in_list, Op};
-        use crate::types::ToSql;
+        let ids = vec![3, 4, 5];
+        let ops = [eq("foo", &1), eq("bar", &2), in_list("baz", &ids)];
+        let op = and(&ops);
 
-        let op = and(&[eq("foo", &1), eq("bar", &2), in_list("baz", &vec![3, 4, 5])]);
+        let mut params_index = 1;
+        let (query, params) = op.sql(&mut params_index);
+        assert_eq!(query, r#"("foo" = $1 AND "bar" = $2 AND "baz" = ANY($3))"#);
+        assert_eq!(params.len(), 3);
+        assert_eq!(params_index, 4);
+    }
 
-        match op {
-            Op::And(ops) => {
-                assert_eq!(ops.len(), 3);
-                match &ops[0] {
-                    Op::Value(op_value) => {
-                        assert_eq!(op_value.column, "foo");
-                        assert_eq!(op_value.op, "=");
-                        assert_eq!(
-                            op_value
-                                .value
-                                .to_sql(&crate::types::Type::INT4, &mut vec![])
-                                .unwrap(),
-                            1
-                        );
-                    }
-                    _ => panic!("Expected Op::Value"),
-                }
-                match &ops[1] {
-                    Op::Value(op_value) => {
-                        assert_eq!(op_value.column, "bar");
-                        assert_eq!(op_value.op, "=");
-                        assert_eq!(
-                            op_value
-                                .value
-                                .to_sql(&crate::types::Type::INT4, &mut vec![])
-                                .unwrap(),
-                            2
-                        );
-                    }
-                    _ => panic!("Expected Op::Value"),
-                }
-                match &ops[2] {
-                    Op::Value(op_value) => {
-                        assert_eq!(op_value.column, "baz");
-                        assert_eq!(op_value.op, "IN");
-                        assert_eq!(
-                            op_value
-                                .value
-                                .to_sql(&crate::types::Type::INT4, &mut vec![])
-                                .unwrap(),
-                            vec![3, 4, 5]
-                        );
-                    }
-                    _ => panic!("Expected Op::Value"),
-                }
-            }
-            _ => panic!("Expected Op::And"),
+    #[test]
+    fn test_ops() {
+        let cases = [
+            (ne("a", &1), r#""a" <> $1"#),
+            (gt("a", &1), r#""a" > $1"#),
+            (gte("a", &1), r#""a" >= $1"#),
+            (lt("a", &1), r#""a" < $1"#),
+            (lte("a", &1), r#""a" <= $1"#),
+            (like("a", &"x%"), r#""a" LIKE $1"#),
+            (ilike("a", &"x%"), r#""a" ILIKE $1"#),
+            (eq_ignore_case("a", &"X"), r#"LOWER("a") = LOWER($1)"#),
+            (not_in("a", &[1, 2]), r#""a" <> ALL($1)"#),
+            (in_array("a", vec![1, 2]), r#""a" = ANY($1)"#),
+            (is_distinct_from("a", &1), r#""a" IS DISTINCT FROM $1"#),
+        ];
+        for (op, expected) in cases {
+            let mut params_index = 1;
+            let (query, params) = op.sql(&mut params_index);
+            assert_eq!(query, expected);
+            assert_eq!(params.len(), 1);
+            assert_eq!(params_index, 2);
         }
     }
+
+    #[test]
+    fn test_row() {
+        let mut params_index = 2;
+        let op = row_eq(&["team_id", "user_id"], &[&1, &2]);
+        let (query, params) = op.sql(&mut params_index);
+        assert_eq!(query, r#"("team_id", "user_id") = ($2, $3)"#);
+        assert_eq!(params.len(), 2);
+        assert_eq!(params_index, 4);
+
+        let (query, _) = row_gt(&["id"], &[&1]).sql(&mut params_index);
+        assert_eq!(query, r#""id" > $4"#);
+    }
+
+    #[test]
+    fn test_in_tuples() {
+        let mut params_index = 1;
+        let pairs = [(1, "a".to_string()), (2, "b".to_string())];
+        let op = in_tuples(&["team_id", "name"], &pairs);
+        let (query, params) = op.sql(&mut params_index);
+        assert_eq!(
+            query,
+            r#"("team_id", "name") IN (SELECT * FROM UNNEST($1::int4[], $2::text[]))"#
+        );
+        assert_eq!(params.len(), 2);
+        assert_eq!(params_index, 3);
+    }
+
+    #[test]
+    fn test_order_by() {
+        assert_eq!(asc("name").sql(), r#""name" ASC"#);
+        assert_eq!(
+            desc("last_seen").nulls_last().sql(),
+            r#""last_seen" DESC NULLS LAST"#
+        );
+        assert_eq!(
+            asc("name").collate("C").nulls_first().sql(),
+            r#""name" COLLATE "C" ASC NULLS FIRST"#
+        );
+        assert_eq!(
+            desc("name").collate("pg_catalog.C").sql(),
+            r#""name" COLLATE "pg_catalog"."C" DESC"#
+        );
+    }
+
+    #[test]
+    fn test_not_and_empty() {
+        let mut params_index = 1;
+        let op = not(or(&[]));
+        let (query, params) = op.sql(&mut params_index);
+        assert_eq!(query, "NOT (FALSE)");
+        assert!(params.is_empty());
+
+        let (query, _) = and(&[]).sql(&mut params_index);
+        assert_eq!(query, "TRUE");
+    }
+
+    #[test]
+    fn test_param_counts() {
+        let ops = [
+            is_null("a"),
+            between("b", &1, &10),
+            is_not_null("c"),
+            eq("d", &2),
+        ];
+        let mut params_index = 1;
+        let op = and(&ops);
+        let (query, params) = op.sql(&mut params_index);
+        assert_eq!(
+            query,
+            r#"("a" IS NULL AND "b" BETWEEN $1 AND $2 AND "c" IS NOT NULL AND "d" = $3)"#
+        );
+        assert_eq!(params.len(), 3);
+        assert_eq!(params_index, 4);
+    }
 }
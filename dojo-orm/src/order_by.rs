@@ -1,15 +1,20 @@
-use std::collections::HashMap;
 use std::fmt::Display;
 use std::marker::PhantomData;
+
+use futures_util::Stream;
 use tracing::debug;
 
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{check_column, columns, quote_ident, table};
 use crate::limit::LimitClause;
 use crate::model::Model;
-use crate::ops::Op;
-use crate::pool::*;
+use crate::ops::{where_sql, Op};
+use crate::pagination::Page;
+use crate::stream::query_stream;
 use crate::types::ToSql;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Order {
     Asc,
     Desc,
@@ -24,75 +29,226 @@ impl Display for Order {
     }
 }
 
-pub struct OrderByClause<'a, T>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+impl Display for Nulls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Nulls::First => write!(f, "NULLS FIRST"),
+            Nulls::Last => write!(f, "NULLS LAST"),
+        }
+    }
+}
+
+/// One `ORDER BY` term, built with `asc` / `desc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderBy<'a> {
+    pub column: &'a str,
+    pub order: Order,
+    pub nulls: Option<Nulls>,
+    pub collate: Option<&'a str>,
+}
+
+impl<'a> OrderBy<'a> {
+    pub fn new(column: &'a str, order: Order) -> Self {
+        Self {
+            column,
+            order,
+            nulls: None,
+            collate: None,
+        }
+    }
+
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls = Some(Nulls::First);
+        self
+    }
+
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls = Some(Nulls::Last);
+        self
+    }
+
+    pub fn collate(mut self, collation: &'a str) -> Self {
+        self.collate = Some(collation);
+        self
+    }
+
+    /// The same order walked backwards, with `NULLS FIRST` / `LAST` swapped to match.
+    pub fn reverse(&self) -> Self {
+        Self {
+            order: match self.order {
+                Order::Asc => Order::Desc,
+                Order::Desc => Order::Asc,
+            },
+            nulls: self.nulls.map(|nulls| match nulls {
+                Nulls::First => Nulls::Last,
+                Nulls::Last => Nulls::First,
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Checks the column is declared by `T`.
+    pub(crate) fn validate<T: Model>(&self) -> Result<()> {
+        check_column::<T>(self.column)
+    }
+
+    /// The quoted column with its collation, as compared by keyset conditions.
+    pub(crate) fn column_sql(&self) -> String {
+        let mut sql = quote_ident(self.column);
+        if let Some(collate) = self.collate {
+            // a schema-qualified collation like `pg_catalog.C` is quoted part by part
+            let collate = collate.split('.').map(quote_ident).collect::<Vec<_>>();
+            sql.push_str(" COLLATE ");
+            sql.push_str(&collate.join("."));
+        }
+        sql
+    }
+
+    pub fn sql(&self) -> String {
+        let mut sql = self.column_sql();
+        sql.push(' ');
+        sql.push_str(&self.order.to_string());
+        if let Some(nulls) = self.nulls {
+            sql.push(' ');
+            sql.push_str(&nulls.to_string());
+        }
+        sql
+    }
+}
+
+/// Renders ` ORDER BY ...`, or nothing when there are no orders.
+pub(crate) fn order_by_sql<T: Model>(orders: &[OrderBy]) -> Result<String> {
+    if orders.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut terms = vec![];
+    for order in orders {
+        order.validate::<T>()?;
+        terms.push(order.sql());
+    }
+
+    Ok(format!(" ORDER BY {}", terms.join(", ")))
+}
+
+pub struct OrderByClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub(crate) pool: &'a Pool<PostgresConnectionManager<NoTls>>,
+    pub(crate) executor: &'a E,
     pub(crate) params: &'a [&'a (dyn ToSql + Sync)],
     pub(crate) ops: &'a [Op<'a>],
-    pub(crate) orders: HashMap<&'a str, Order>,
+    pub(crate) orders: Vec<OrderBy<'a>>,
     pub(crate) _t: PhantomData<T>,
 }
 
-impl<'a, T> OrderByClause<'a, T>
+impl<'a, T, E> OrderByClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub fn limit(&'a mut self, limit: i32) -> LimitClause<'a, T> {
+    /// Adds a tie-breaker, applied after the orders already given.
+    pub fn order_by(&'a mut self, order: OrderBy<'a>) -> &'a mut Self {
+        self.orders.push(order);
+        self
+    }
+
+    pub fn limit(&'a self, limit: i64) -> LimitClause<'a, T, E> {
         LimitClause {
-            params: &self.params,
-            ops: &self.ops,
-            orders: Some(&self.orders),
-            limit,
+            executor: self.executor,
+            params: self.params,
+            ops: self.ops,
+            orders: &self.orders,
+            limit: Some(limit),
+            offset: None,
             _t: PhantomData,
         }
     }
 
-    pub fn build(&'a self) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    fn build_select(&'a self, select: &str) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
         let mut params_index = 1;
 
         let mut params = self.params.to_vec();
         let mut query = "SELECT ".to_string();
-        query.push_str(&T::COLUMNS.join(", "));
+        query.push_str(select);
         query.push_str(" FROM ");
-        query.push_str(T::NAME);
+        query.push_str(&table::<T>());
 
-        let mut ands = vec![];
-        for op in self.ops {
-            let (q, p) = op.sql(&mut params_index);
-            ands.push(q);
-            params.extend_from_slice(&p);
-        }
-        if !query.is_empty() {
-            let and = ands.join(" AND ");
-            query.push_str(" WHERE ");
-            query.push_str(&and);
-        }
+        query.push_str(&where_sql::<T>(self.ops, &mut params_index, &mut params)?);
 
-        let mut orders = vec![];
-        for (column, order) in &self.orders {
-            orders.push(format!("{} {}", column, order.to_string()));
-        }
-        if !orders.is_empty() {
-            let order = orders.join(", ");
-            query.push_str(" ORDER BY ");
-            query.push_str(&order);
-        }
+        Ok((query, params))
+    }
 
-        query.push_str(" LIMIT 1");
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        let (mut query, params) = self.build_select(&columns::<T>())?;
+        query.push_str(&order_by_sql::<T>(&self.orders)?);
 
-        (query, params)
+        Ok((query, params))
     }
 
-    pub async fn first(&'a mut self) -> anyhow::Result<Option<T>> {
-        let (query, params) = self.build();
+    async fn count(&'a self) -> Result<i64> {
+        let (query, params) = self.build_select("COUNT(*)")?;
         debug!("query: {}, params: {:?}", query, params);
-        let conn = self.pool.get().await?;
+        let row = self.executor.query_one(query.as_str(), &params).await?;
+
+        row.try_get(0).map_err(|e| Error::decode("count", e))
+    }
 
-        conn.query_opt(query.as_str(), &params)
+    /// Fetches page `page` (from 1) of `per_page` items, with the total row count.
+    pub async fn paginate(&'a self, page: i64, per_page: i64) -> Result<Page<T>> {
+        let page = page.max(1);
+        let per_page = per_page.max(1);
+
+        let (query, params) = self.build()?;
+        let query = format!(
+            "{} LIMIT {} OFFSET {}",
+            query,
+            per_page,
+            (page - 1).saturating_mul(per_page)
+        );
+        debug!("query: {}, params: {:?}", query, params);
+        let query_fut = self.executor.query(query.as_str(), &params);
+        let (rows, total) = tokio::try_join!(query_fut, self.count())?;
+
+        let mut items = vec![];
+        for row in rows {
+            items.push(T::from_row(row)?);
+        }
+
+        Ok(Page::new(items, page, per_page, total))
+    }
+
+    pub async fn first(&'a self) -> Result<Option<T>> {
+        let (query, params) = self.build()?;
+        let query = format!("{} LIMIT 1", query);
+        debug!("query: {}, params: {:?}", query, params);
+        self.executor
+            .query_opt(query.as_str(), &params)
             .await?
             .map(T::from_row)
             .transpose()
     }
+
+    /// Streams the rows instead of collecting them. See `WhereSelectClause::stream`.
+    pub fn stream(&'a self) -> impl Stream<Item = Result<T>> + 'a {
+        query_stream(self.executor, self.build())
+    }
+
+    pub async fn all(&'a self) -> Result<Vec<T>> {
+        let (query, params) = self.build()?;
+        debug!("query: {}, params: {:?}", query, params);
+        let mut rows = vec![];
+        for row in self.executor.query(query.as_str(), &params).await? {
+            rows.push(T::from_row(row)?);
+        }
+
+        Ok(rows)
+    }
 }
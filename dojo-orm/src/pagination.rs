@@ -1,71 +1,312 @@
 use std::error::Error;
 use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
 
-use crate::types::{accepts, to_sql_checked, IsNull, ToSql, Type};
-use anyhow::Result;
+use crate::types::{to_sql_checked, IsNull, ToSql, Type};
+#[cfg(feature = "graphql")]
 use async_graphql::connection::{Connection, CursorType, Edge};
+#[cfg(feature = "graphql")]
 use async_graphql::{
     InputValueError, InputValueResult, OutputType, Scalar, ScalarType, SimpleObject, Value,
 };
-use base64ct::{Base64, Encoding};
+use base64ct::{Base64UrlUnpadded, Encoding};
 use bytes::BytesMut;
-use chrono::NaiveDateTime;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use thiserror::Error;
+use uuid::Uuid;
 
-pub trait CursorExt<C: CursorType> {
+/// Implemented by `#[derive(Model)]` from the fields marked `#[dojo(cursor)]`.
+pub trait CursorExt<C> {
     fn cursor(&self) -> C;
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct Cursor {
-    pub field: String,
-    pub value: i64,
+/// A value of one cursor column, bound as the column's type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", content = "v", rename_all = "snake_case")]
+pub enum CursorValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Uuid(#[serde(with = "uuid_string")] Uuid),
+    Timestamp(NaiveDateTime),
+    TimestampTz(DateTime<Utc>),
+    Date(NaiveDate),
+}
+
+mod uuid_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(uuid: &Uuid, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(uuid)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Uuid, D::Error> {
+        let s = String::deserialize(d)?;
+        Uuid::parse_str(&s).map_err(serde::de::Error::custom)
+    }
 }
 
-impl ToSql for Cursor {
+impl ToSql for CursorValue {
     fn to_sql(
         &self,
         ty: &Type,
         w: &mut BytesMut,
     ) -> std::result::Result<IsNull, Box<dyn Error + Sync + Send>> {
-        let t = NaiveDateTime::from_timestamp_micros(self.value).unwrap();
-        t.to_sql(ty, w)
+        match self {
+            CursorValue::Null => Ok(IsNull::Yes),
+            CursorValue::Bool(v) => v.to_sql_checked(ty, w),
+            CursorValue::Int(v) => match *ty {
+                Type::INT2 => i16::try_from(*v)?.to_sql_checked(ty, w),
+                Type::INT4 => i32::try_from(*v)?.to_sql_checked(ty, w),
+                _ => v.to_sql_checked(ty, w),
+            },
+            CursorValue::Float(v) => match *ty {
+                Type::FLOAT4 => (*v as f32).to_sql_checked(ty, w),
+                _ => v.to_sql_checked(ty, w),
+            },
+            CursorValue::Text(v) => v.to_sql_checked(ty, w),
+            CursorValue::Uuid(v) => v.to_sql_checked(ty, w),
+            CursorValue::Timestamp(v) => match *ty {
+                Type::TIMESTAMPTZ => v.and_utc().to_sql_checked(ty, w),
+                _ => v.to_sql_checked(ty, w),
+            },
+            CursorValue::TimestampTz(v) => match *ty {
+                Type::TIMESTAMP => v.naive_utc().to_sql_checked(ty, w),
+                _ => v.to_sql_checked(ty, w),
+            },
+            CursorValue::Date(v) => v.to_sql_checked(ty, w),
+        }
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <bool as ToSql>::accepts(ty)
+            || <i16 as ToSql>::accepts(ty)
+            || <i32 as ToSql>::accepts(ty)
+            || <i64 as ToSql>::accepts(ty)
+            || <f32 as ToSql>::accepts(ty)
+            || <f64 as ToSql>::accepts(ty)
+            || <String as ToSql>::accepts(ty)
+            || <Uuid as ToSql>::accepts(ty)
+            || <NaiveDateTime as ToSql>::accepts(ty)
+            || <DateTime<Utc> as ToSql>::accepts(ty)
+            || <NaiveDate as ToSql>::accepts(ty)
     }
 
-    accepts!(TIMESTAMP);
     to_sql_checked!();
 }
 
+macro_rules! impl_from_cursor_value {
+    ($($ty:ty => $variant:ident $(as $into:ty)?),* $(,)?) => {
+        $(
+            impl From<$ty> for CursorValue {
+                fn from(value: $ty) -> Self {
+                    CursorValue::$variant((value $(as $into)?).into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_cursor_value! {
+    bool => Bool,
+    i16 => Int as i64,
+    i32 => Int as i64,
+    i64 => Int,
+    f32 => Float as f64,
+    f64 => Float,
+    String => Text,
+    &str => Text,
+    Uuid => Uuid,
+    NaiveDateTime => Timestamp,
+    DateTime<Utc> => TimestampTz,
+    NaiveDate => Date,
+}
+
+impl<T: Into<CursorValue>> From<Option<T>> for CursorValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(CursorValue::Null)
+    }
+}
+
+/// A row's value for each column a keyset-paginated listing is ordered by.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub values: Vec<(String, CursorValue)>,
+}
+
 impl Cursor {
-    pub fn new(field: String, value: i64) -> Self {
-        Self { field, value }
+    /// `value` must convert from the column's own Rust type, see the README.
+    pub fn new(field: impl Into<String>, value: impl Into<CursorValue>) -> Self {
+        Self {
+            values: vec![(field.into(), value.into())],
+        }
     }
+
+    /// Adds another column, e.g. the tiebreaker after a timestamp.
+    pub fn and(mut self, field: impl Into<String>, value: impl Into<CursorValue>) -> Self {
+        self.values.push((field.into(), value.into()));
+        self
+    }
+
+    pub fn value(&self, field: &str) -> Option<&CursorValue> {
+        self.values
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value)
+    }
+
+    /// Encodes the cursor with the key set by [`set_cursor_key`].
     pub fn encode(&self) -> String {
-        Base64::encode_string(format!("{}:{}", self.field, self.value).as_bytes())
+        let json = serde_json::to_vec(&self.values).expect("cursor values serialize");
+        Base64UrlUnpadded::encode_string(&cursor_key().seal(&json))
     }
 
+    /// Decodes a cursor made by [`Cursor::encode`] with the same key.
     pub fn decode(encoded: &str) -> Result<Self, OffsetEncodedError> {
-        let decoded = Base64::decode_vec(encoded).map_err(|_| OffsetEncodedError::InvalidBase64)?;
-        let raw = String::from_utf8(decoded).map_err(OffsetEncodedError::Utf8Error)?;
-        let parts = raw
-            .as_str()
-            .split(':')
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-
-        let field = parts[0].clone();
-        let value = parts[1]
-            .clone()
-            .parse::<i64>()
-            .map_err(|_| OffsetEncodedError::InvalidCursor)?;
-
-        Ok(Self {
-            field: field.into(),
-            value,
-        })
+        let decoded = Base64UrlUnpadded::decode_vec(encoded)
+            .map_err(|_| OffsetEncodedError::InvalidBase64)?;
+        let json = cursor_key().open(&decoded)?;
+        let values =
+            serde_json::from_slice(&json).map_err(|_| OffsetEncodedError::InvalidCursor)?;
+
+        Ok(Self { values })
     }
 }
 
+/// The version byte encoded cursors start with.
+const SIGNED_V1: u8 = 1;
+const ENCRYPTED_V1: u8 = 2;
+
+const TAG_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// The secret cursors are signed, and optionally encrypted, with.
+#[derive(Clone)]
+pub struct CursorKey {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+    encrypt: bool,
+}
+
+impl CursorKey {
+    /// A key signing cursors, derived from `secret`, at least 32 random bytes.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        let derive = |label: &[u8]| -> [u8; 32] {
+            let mut mac =
+                <HmacSha256 as Mac>::new_from_slice(secret.as_ref()).expect("any key length");
+            mac.update(label);
+            mac.finalize().into_bytes().into()
+        };
+
+        Self {
+            signing: derive(b"dojo-orm cursor signing"),
+            encryption: derive(b"dojo-orm cursor encryption"),
+            encrypt: false,
+        }
+    }
+
+    /// Also encrypts cursors. Cursors encoded before switching are still accepted.
+    pub fn encrypted(mut self) -> Self {
+        self.encrypt = true;
+        self
+    }
+
+    fn random() -> Self {
+        Self::new(ChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    fn seal(&self, json: &[u8]) -> Vec<u8> {
+        if self.encrypt {
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let cipher = ChaCha20Poly1305::new(&self.encryption.into());
+            let payload = Payload {
+                msg: json,
+                aad: &[ENCRYPTED_V1],
+            };
+            let ciphertext = cipher.encrypt(&nonce, payload).expect("cursor encrypts");
+
+            [&[ENCRYPTED_V1], nonce.as_slice(), &ciphertext].concat()
+        } else {
+            let mut mac = self.mac();
+            mac.update(&[SIGNED_V1]);
+            mac.update(json);
+
+            [&[SIGNED_V1], json, &mac.finalize().into_bytes()].concat()
+        }
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, OffsetEncodedError> {
+        match sealed.split_first() {
+            Some((&SIGNED_V1, rest)) if rest.len() >= TAG_LEN => {
+                let (json, tag) = rest.split_at(rest.len() - TAG_LEN);
+                let mut mac = self.mac();
+                mac.update(&[SIGNED_V1]);
+                mac.update(json);
+                mac.verify_slice(tag)
+                    .map_err(|_| OffsetEncodedError::InvalidSignature)?;
+
+                Ok(json.to_vec())
+            }
+            Some((&ENCRYPTED_V1, rest)) if rest.len() >= NONCE_LEN => {
+                let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+                let cipher = ChaCha20Poly1305::new(&self.encryption.into());
+                let payload = Payload {
+                    msg: ciphertext,
+                    aad: &[ENCRYPTED_V1],
+                };
+                cipher
+                    .decrypt(Nonce::from_slice(nonce), payload)
+                    .map_err(|_| OffsetEncodedError::InvalidSignature)
+            }
+            Some((&SIGNED_V1 | &ENCRYPTED_V1, _)) => Err(OffsetEncodedError::InvalidCursor),
+            Some((&version, _)) => Err(OffsetEncodedError::UnsupportedVersion(version)),
+            None => Err(OffsetEncodedError::InvalidCursor),
+        }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        <HmacSha256 as Mac>::new_from_slice(&self.signing).expect("any key length")
+    }
+}
+
+impl std::fmt::Debug for CursorKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorKey")
+            .field("encrypt", &self.encrypt)
+            .finish_non_exhaustive()
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+static CURSOR_KEY: RwLock<Option<Arc<CursorKey>>> = RwLock::new(None);
+
+/// Sets the key every [`Cursor`] is encoded and decoded with from now on.
+pub fn set_cursor_key(key: CursorKey) {
+    *CURSOR_KEY.write().unwrap() = Some(Arc::new(key));
+}
+
+fn cursor_key() -> Arc<CursorKey> {
+    if let Some(key) = CURSOR_KEY.read().unwrap().as_ref() {
+        return key.clone();
+    }
+
+    CURSOR_KEY
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(CursorKey::random()))
+        .clone()
+}
+
+#[cfg(feature = "graphql")]
 #[Scalar]
 impl ScalarType for Cursor {
     fn parse(value: Value) -> InputValueResult<Self> {
@@ -84,6 +325,7 @@ impl ScalarType for Cursor {
     }
 }
 
+#[cfg(feature = "graphql")]
 impl CursorType for Cursor {
     type Error = OffsetEncodedError;
 
@@ -96,9 +338,25 @@ impl CursorType for Cursor {
     }
 }
 
+#[cfg(feature = "graphql")]
 #[derive(SimpleObject, Debug, Clone)]
 pub struct AdditionalFields {
-    pub total_nodes: i64,
+    /// `None` when the count was skipped.
+    pub total_nodes: Option<i64>,
+}
+
+/// How cursor pagination computes `total_nodes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CountMode {
+    /// A separate `COUNT(*)` with the same filters, run concurrently with the page.
+    #[default]
+    Exact,
+    /// `COUNT(*) OVER()` in the page query itself, saving a round-trip.
+    Window,
+    /// The planner's row estimate for the filtered query, from `EXPLAIN`.
+    Estimated,
+    /// Don't count, e.g. when the client didn't select `totalNodes`.
+    Skip,
 }
 
 #[derive(Debug, Error)]
@@ -106,155 +364,223 @@ pub enum OffsetEncodedError {
     #[error("invalid base64")]
     InvalidBase64,
 
-    #[error("invalid utf8: {0}")]
-    Utf8Error(#[from] std::string::FromUtf8Error),
-
     #[error("invalid cursor")]
     InvalidCursor,
+
+    /// The cursor was modified, or encoded with a different [`CursorKey`].
+    #[error("invalid cursor signature")]
+    InvalidSignature,
+
+    #[error("unsupported cursor version {0}")]
+    UnsupportedVersion(u8),
+}
+
+/// One page of a page-number listing, as returned by `paginate`. Pages start at 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub total_pages: i64,
+    pub has_next: bool,
+}
+
+impl<T> Page<T> {
+    /// `per_page` is clamped to at least 1, as in `paginate`.
+    pub fn new(items: Vec<T>, page: i64, per_page: i64, total: i64) -> Self {
+        let per_page = per_page.max(1);
+        // rounds up without `total + per_page - 1`, which overflows for huge page sizes
+        let total_pages = total / per_page + i64::from(total % per_page != 0);
+        Self {
+            items,
+            page,
+            per_page,
+            total,
+            total_pages,
+            has_next: page < total_pages,
+        }
+    }
+
+    pub fn has_previous(&self) -> bool {
+        self.page > 1
+    }
+
+    /// Converts the items, e.g. from rows into GraphQL objects.
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+            total_pages: self.total_pages,
+            has_next: self.has_next,
+        }
+    }
+
+    /// The offset of the first item on this page, saturating for pages far past the end.
+    pub fn offset(&self) -> i64 {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+}
+
+#[cfg(feature = "graphql")]
+#[derive(SimpleObject, Debug, Clone)]
+pub struct PageFields {
+    pub page: i64,
+    pub per_page: i64,
+    pub total_nodes: i64,
+    pub total_pages: i64,
+}
+
+/// Edges are keyed by the row's offset in the whole listing.
+#[cfg(feature = "graphql")]
+impl<N> From<Page<N>> for Connection<i64, N, PageFields>
+where
+    N: OutputType,
+{
+    fn from(value: Page<N>) -> Self {
+        let offset = value.offset();
+        let mut connection = Connection::with_additional_fields(
+            value.has_previous(),
+            value.has_next,
+            PageFields {
+                page: value.page,
+                per_page: value.per_page,
+                total_nodes: value.total,
+                total_pages: value.total_pages,
+            },
+        );
+
+        connection.edges.extend(
+            value
+                .items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| Edge::new(offset.saturating_add(index as i64), item)),
+        );
+
+        connection
+    }
 }
 
+/// One page of a cursor connection, in connection order.
 #[derive(Debug)]
 pub struct Pagination<C, T>
 where
     T: CursorExt<C>,
-    C: CursorType + Send + Sync,
 {
     pub items: Vec<T>,
-    pub before: Option<Cursor>,
-    pub after: Option<Cursor>,
-    pub limit: i64,
-    pub total_nodes: i64,
+    pub has_previous: bool,
+    pub has_next: bool,
+    pub total_nodes: Option<i64>,
     _phantom: PhantomData<C>,
 }
 
 impl<C, T> Pagination<C, T>
 where
     T: CursorExt<C>,
-    C: CursorType + Send + Sync,
 {
     pub fn new(
         items: Vec<T>,
-        before: Option<Cursor>,
-        after: Option<Cursor>,
-        limit: i64,
-        total_nodes: i64,
+        has_previous: bool,
+        has_next: bool,
+        total_nodes: Option<i64>,
     ) -> Self {
         Self {
             items,
-            before,
-            after,
-            limit,
+            has_previous,
+            has_next,
             total_nodes,
             _phantom: PhantomData,
         }
     }
-
-    /// Returns a tuple of (has_previous, has_next)
-    fn has_page(&self) -> (bool, bool) {
-        let item_size = self.items.len();
-        if self.after.is_some() {
-            match item_size {
-                0 => (false, false),
-                size if size - 1 <= self.limit as usize => (true, false),
-                // if size is greater than 2, it means we have a next page and previous page
-                // [A](cursor) -> (B) -> (C) -> (D) -> [E](check next)
-                size if size > 2 => (true, true),
-                // if size is less than 2, it means we have a previous page but no no page
-                // [A](cursor) -> (C)
-                _ => (true, false),
-            }
-        } else if self.before.is_some() {
-            match item_size {
-                0 => (false, false),
-                // if size - 1 is less than or equal to take, it means we have a previous page but no next page
-                // (check previous)[A] <- (B) <- (cursor)[C]
-                size if size - 1 <= self.limit as usize => (false, true),
-                // if size is greater than 2, it means we have a next page and previous page
-                // (check previous)[A] <- (B) <- (C) <- (D) <- (cursor)[E]
-                size if size > 2 => (true, true),
-                // otherwise, it means we have a next page but no previous page
-                _ => (false, true),
-            }
-        } else {
-            match item_size {
-                0 => (false, false),
-                // if size - 1 is greater than 2, it means we have a next page and no previous page
-                size if size > self.limit as usize => (false, true),
-                // otherwise, it means we have no next page and no previous page
-                _ => (false, false),
-            }
-        }
-    }
 }
 
+#[cfg(feature = "graphql")]
 impl<C, N> From<Pagination<C, N>> for Connection<C, N, AdditionalFields>
 where
     C: CursorType + Send + Sync,
     N: OutputType + CursorExt<C>,
 {
     fn from(value: Pagination<C, N>) -> Self {
-        let item_size = value.items.len();
-        let (has_previous, has_next) = value.has_page();
         let mut connection = Connection::with_additional_fields(
-            has_previous,
-            has_next,
+            value.has_previous,
+            value.has_next,
             AdditionalFields {
                 total_nodes: value.total_nodes,
             },
         );
 
-        let mut edges = vec![];
-        for (index, item) in value.items.into_iter().enumerate() {
-            if value.after.is_some() || value.before.is_some() {
-                match item_size {
-                    // if size is less than or equal to take and after is present, then we need to skip the first item
-                    // after: [A](cursor) -> (B) -> [C](check next)
-                    // if size is less than or equal to take and before is present, then we need to skip the last item
-                    // before: (check previous)[A] <- (B) <- (cursor)[C]
-                    size if size - 1 <= value.limit as usize => {
-                        if index == 0 {
-                            continue;
-                        }
-                    }
-                    // if size is greater than 2, then we need to skip the first and last item
-                    // [A](cursor) -> (B) -> (C) -> (D) -> [E](check next)
-                    // (check previous)[A] <- (B) <- (C) <- (D) <- (cursor)[E]
-                    size if size > 2 => {
-                        if index == 0 {
-                            continue;
-                        }
-                        if index == item_size - 1 {
-                            break;
-                        }
-                    }
-                    // otherwise, we need to skip the first item
-                    // when size is 2: [A](cursor) -> (B)
-                    // when size is 1: [A](cursor)
-                    // when size is 0: _
-                    _ => {
-                        if index == 0 {
-                            continue;
-                        }
-                    }
-                }
-            } else if item_size > value.limit as usize {
-                // if size is greater than take, then we need to skip the last item
-                // (A) -> (B) -> (C) -> (D) -> [E](check next)
-                if index == item_size - 1 {
-                    continue;
-                }
-            }
-
+        connection.edges.extend(value.items.into_iter().map(|item| {
             let cursor = item.cursor();
-            edges.push(Edge::new(cursor, item));
-        }
+            Edge::new(cursor, item)
+        }));
+
+        connection
+    }
+}
+
+/// A cursor page for REST responses, each item with its encoded cursor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorPage<T> {
+    pub edges: Vec<CursorEdge<T>>,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+    pub has_previous: bool,
+    pub has_next: bool,
+    /// `None` when the count was skipped.
+    pub total_nodes: Option<i64>,
+}
 
-        if value.before.is_some() {
-            edges.reverse();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorEdge<T> {
+    pub cursor: String,
+    pub node: T,
+}
+
+impl<T> CursorPage<T> {
+    /// Converts the nodes, e.g. from rows into response bodies. Cursors are kept.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> CursorPage<U> {
+        CursorPage {
+            edges: self
+                .edges
+                .into_iter()
+                .map(|edge| CursorEdge {
+                    cursor: edge.cursor,
+                    node: f(edge.node),
+                })
+                .collect(),
+            start_cursor: self.start_cursor,
+            end_cursor: self.end_cursor,
+            has_previous: self.has_previous,
+            has_next: self.has_next,
+            total_nodes: self.total_nodes,
         }
+    }
+}
 
-        connection.edges.extend(edges);
+impl<T> From<Pagination<Cursor, T>> for CursorPage<T>
+where
+    T: CursorExt<Cursor>,
+{
+    fn from(value: Pagination<Cursor, T>) -> Self {
+        let edges = value
+            .items
+            .into_iter()
+            .map(|item| CursorEdge {
+                cursor: item.cursor().encode(),
+                node: item,
+            })
+            .collect::<Vec<_>>();
 
-        connection
+        Self {
+            start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+            end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+            edges,
+            has_previous: value.has_previous,
+            has_next: value.has_next,
+            total_nodes: value.total_nodes,
+        }
     }
 }
@@ -0,0 +1,114 @@
+use std::marker::PhantomData;
+
+use tracing::debug;
+
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::ident::{check_column, quote_ident, table};
+use crate::limit::limit_sql;
+use crate::model::{Model, Projection};
+use crate::ops::{where_sql, Op};
+use crate::order_by::{order_by_sql, OrderBy};
+use crate::types::ToSql;
+
+/// The select list of `P`: its columns, checked against `T`, and its computed fields.
+pub(crate) fn projection_sql<T: Model, P: Projection>() -> Result<String> {
+    let mut select = vec![];
+    for (name, expr) in P::SELECT {
+        match expr {
+            Some(expr) => select.push(format!("{} AS {}", expr, quote_ident(name))),
+            None => {
+                check_column::<T>(name)?;
+                select.push(quote_ident(name));
+            }
+        }
+    }
+
+    Ok(select.join(", "))
+}
+
+/// Reads rows of `T` as the partial row type `P`, see [`Projection`].
+pub struct SelectClause<'a, T, P, E>
+where
+    T: Model,
+    P: Projection,
+    E: Executor,
+{
+    pub(crate) executor: &'a E,
+    pub(crate) params: &'a [&'a (dyn ToSql + Sync)],
+    pub(crate) ops: &'a [Op<'a>],
+    pub(crate) orders: Vec<OrderBy<'a>>,
+    pub(crate) limit: Option<i64>,
+    pub(crate) offset: Option<i64>,
+    pub(crate) _t: PhantomData<(T, P)>,
+}
+
+impl<'a, T, P, E> SelectClause<'a, T, P, E>
+where
+    T: Model,
+    P: Projection,
+    E: Executor,
+{
+    /// Orders by a column of `T`, which needn't be selected.
+    pub fn order_by(&'a mut self, order: OrderBy<'a>) -> &'a mut Self {
+        self.orders.push(order);
+        self
+    }
+
+    pub fn limit(&'a mut self, limit: i64) -> &'a mut Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first `offset` rows.
+    pub fn offset(&'a mut self, offset: i64) -> &'a mut Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    fn build_with_limit(
+        &'a self,
+        limit: Option<i64>,
+    ) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        let mut params_index = 1;
+
+        let mut params = self.params.to_vec();
+        let mut query = "SELECT ".to_string();
+        query.push_str(&projection_sql::<T, P>()?);
+        query.push_str(" FROM ");
+        query.push_str(&table::<T>());
+
+        query.push_str(&where_sql::<T>(self.ops, &mut params_index, &mut params)?);
+
+        query.push_str(&order_by_sql::<T>(&self.orders)?);
+
+        query.push_str(&limit_sql(limit, self.offset)?);
+
+        Ok((query, params))
+    }
+
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        self.build_with_limit(self.limit)
+    }
+
+    pub async fn first(&'a self) -> Result<Option<P>> {
+        let (query, params) = self.build_with_limit(Some(1))?;
+        debug!("query: {}, params: {:?}", query, params);
+        self.executor
+            .query_opt(query.as_str(), &params)
+            .await?
+            .map(P::from_row)
+            .transpose()
+    }
+
+    pub async fn all(&'a self) -> Result<Vec<P>> {
+        let (query, params) = self.build()?;
+        debug!("query: {}, params: {:?}", query, params);
+        let mut rows = vec![];
+        for row in self.executor.query(query.as_str(), &params).await? {
+            rows.push(P::from_row(row)?);
+        }
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,31 @@
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::model::Model;
+use crate::types::ToSql;
+
+/// Streams the rows of a built query, holding the connection until dropped.
+pub(crate) fn query_stream<'a, E, T>(
+    executor: &'a E,
+    built: Result<(String, Vec<&'a (dyn ToSql + Sync)>)>,
+) -> impl Stream<Item = Result<T>> + 'a
+where
+    E: Executor,
+    T: Model + 'a,
+{
+    stream::once(async move {
+        let (query, params) = built?;
+        debug!("query: {}, params: {:?}", query, params);
+        let client = executor.checkout().await?;
+        let rows = client.query_raw(query.as_str(), params).await?;
+
+        Ok::<_, Error>(rows.map(move |row| {
+            // the rows own the connection they're read from
+            let _client = &client;
+            T::from_row(row?)
+        }))
+    })
+    .try_flatten()
+}
@@ -0,0 +1,466 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::{FutureExt, Stream};
+use tokio_postgres::tls::MakeTlsConnect;
+use tokio_postgres::{Client, Error, Row, Socket};
+use tracing::debug;
+
+use crate::database::Database;
+use crate::error::Result;
+use crate::executor::{ClientGuard, Executor};
+use crate::model::{Model, PrimaryKey, UpdateModel};
+use crate::pagination::{Cursor, CursorExt};
+use crate::pool::*;
+use crate::types::ToSql;
+use crate::upsert::UpsertClause;
+use crate::where_select::WhereSelectClause;
+use crate::where_update::WhereUpdateClause;
+
+/// Sends `query` without waiting for it to finish.
+pub(crate) fn send_batch(client: &Client, query: &str) {
+    debug!("query: {}", query);
+    if let Some(Err(e)) = client.batch_execute(query).now_or_never() {
+        debug!("failed to send {}: {}", query, e);
+    }
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Debug, Clone, Copy)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl Display for IsolationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IsolationLevel::ReadUncommitted => write!(f, "READ UNCOMMITTED"),
+            IsolationLevel::ReadCommitted => write!(f, "READ COMMITTED"),
+            IsolationLevel::RepeatableRead => write!(f, "REPEATABLE READ"),
+            IsolationLevel::Serializable => write!(f, "SERIALIZABLE"),
+        }
+    }
+}
+
+pub struct TransactionBuilder<'a, Tls>
+where
+    Tls: MakeTlsConnect<Socket>,
+    PostgresConnectionManager<Tls>: ManageConnection<Connection = Client, Error = Error>,
+{
+    pub(crate) db: &'a Database<Tls>,
+    pub(crate) isolation_level: Option<IsolationLevel>,
+    pub(crate) read_only: Option<bool>,
+    pub(crate) deferrable: Option<bool>,
+}
+
+impl<'a, Tls> TransactionBuilder<'a, Tls>
+where
+    Tls: MakeTlsConnect<Socket>,
+    PostgresConnectionManager<Tls>: ManageConnection<Connection = Client, Error = Error>,
+{
+    pub fn isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.isolation_level = Some(isolation_level);
+        self
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    /// Only takes effect for `SERIALIZABLE READ ONLY` transactions.
+    pub fn deferrable(mut self, deferrable: bool) -> Self {
+        self.deferrable = Some(deferrable);
+        self
+    }
+
+    fn build(&self) -> String {
+        let mut modes = vec![];
+        if let Some(isolation_level) = self.isolation_level {
+            modes.push(format!("ISOLATION LEVEL {}", isolation_level));
+        }
+        if let Some(read_only) = self.read_only {
+            modes.push(if read_only { "READ ONLY" } else { "READ WRITE" }.to_string());
+        }
+        if let Some(deferrable) = self.deferrable {
+            modes.push(
+                if deferrable {
+                    "DEFERRABLE"
+                } else {
+                    "NOT DEFERRABLE"
+                }
+                .to_string(),
+            );
+        }
+
+        let mut query = "START TRANSACTION".to_string();
+        if !modes.is_empty() {
+            query.push(' ');
+            query.push_str(&modes.join(", "));
+        }
+
+        query
+    }
+
+    pub async fn begin(self) -> Result<Transaction> {
+        let query = self.build();
+        let conn = self.db.pool.get_owned().await?;
+        debug!("query: {}", query);
+        conn.batch_execute(query.as_str()).await?;
+
+        Ok(Transaction {
+            conn: Some(Arc::new(conn)),
+            depth: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Runs `f` inside the transaction, committing when it returns `Ok`.
+    pub async fn run<F, R, E>(self, f: F) -> Result<R, E>
+    where
+        F: for<'t> FnOnce(&'t mut Transaction) -> BoxFuture<'t, Result<R, E>>,
+        E: From<crate::Error>,
+    {
+        let mut tx = self.begin().await?;
+        match f(&mut tx).await {
+            Ok(value) => {
+                tx.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                if let Err(err) = tx.rollback().await {
+                    debug!("failed to rollback transaction: {}", err);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A transaction pinned to a single pooled connection, rolled back if dropped.
+pub struct Transaction {
+    conn: Option<Arc<dyn Deref<Target = Client> + Send + Sync>>,
+    depth: Arc<AtomicUsize>,
+}
+
+/// A savepoint of [`Transaction::transaction`], rolled back to if dropped.
+struct Savepoint {
+    conn: Arc<dyn Deref<Target = Client> + Send + Sync>,
+    depth: Arc<AtomicUsize>,
+    name: String,
+    active: bool,
+}
+
+impl Savepoint {
+    async fn begin(tx: &Transaction) -> Result<Savepoint> {
+        let level = tx.depth.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut savepoint = Savepoint {
+            conn: tx.conn.clone().expect("transaction already finished"),
+            depth: tx.depth.clone(),
+            name: format!("dojo_savepoint_{}", level),
+            active: false,
+        };
+        let query = format!("SAVEPOINT {}", savepoint.name);
+        debug!("query: {}", query);
+        savepoint.conn.batch_execute(&query).await?;
+        savepoint.active = true;
+
+        Ok(savepoint)
+    }
+
+    async fn release(mut self) -> Result<()> {
+        let query = format!("RELEASE SAVEPOINT {}", self.name);
+        debug!("query: {}", query);
+        self.conn.batch_execute(&query).await?;
+        self.active = false;
+
+        Ok(())
+    }
+
+    async fn rollback(mut self) -> Result<()> {
+        self.active = false;
+        let query = format!(
+            "ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0}",
+            self.name
+        );
+        debug!("query: {}", query);
+        Ok(self.conn.batch_execute(&query).await?)
+    }
+}
+
+impl Drop for Savepoint {
+    fn drop(&mut self) {
+        if self.active {
+            send_batch(
+                &self.conn,
+                &format!(
+                    "ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0}",
+                    self.name
+                ),
+            );
+        }
+        self.depth.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Transaction {
+    fn client(&self) -> &Client {
+        self.conn.as_ref().expect("transaction already finished")
+    }
+
+    async fn batch_execute(&self, query: &str) -> Result<()> {
+        debug!("query: {}", query);
+        Ok(self.client().batch_execute(query).await?)
+    }
+
+    pub async fn commit(mut self) -> Result<()> {
+        self.batch_execute("COMMIT").await?;
+        self.conn.take();
+        Ok(())
+    }
+
+    pub async fn rollback(mut self) -> Result<()> {
+        self.batch_execute("ROLLBACK").await?;
+        self.conn.take();
+        Ok(())
+    }
+
+    /// Runs `f` inside a savepoint, releasing it when `f` returns `Ok`.
+    pub async fn transaction<F, R, E>(&mut self, f: F) -> Result<R, E>
+    where
+        F: for<'t> FnOnce(&'t mut Transaction) -> BoxFuture<'t, Result<R, E>>,
+        E: From<crate::Error>,
+    {
+        let savepoint = Savepoint::begin(self).await?;
+        match f(self).await {
+            Ok(value) => {
+                savepoint.release().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                if let Err(err) = savepoint.rollback().await {
+                    debug!("failed to rollback to savepoint: {}", err);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub fn bind<T>(&self) -> WhereSelectClause<T, Self>
+    where
+        T: Model + CursorExt<Cursor> + Sync + Send,
+    {
+        Executor::bind(self)
+    }
+
+    pub async fn insert<T: Model + Sync>(&self, data: &T) -> Result<T> {
+        Executor::insert(self, data).await
+    }
+
+    pub async fn insert_many<T: Model + Sync>(&self, data: &[T]) -> Result<Vec<T>> {
+        Executor::insert_many(self, data).await
+    }
+
+    pub async fn copy_in<T: Model + Sync>(&self, data: &[T]) -> Result<u64> {
+        Executor::copy_in(self, data).await
+    }
+
+    pub async fn copy_in_stream<T, S>(&self, rows: S) -> Result<u64>
+    where
+        T: Model + Send + Sync,
+        S: Stream<Item = T> + Send,
+    {
+        Executor::copy_in_stream(self, rows).await
+    }
+
+    pub fn upsert<'a, T: Model>(&'a self, data: &'a T) -> UpsertClause<'a, T, Self> {
+        Executor::upsert(self, data)
+    }
+
+    pub fn upsert_many<'a, T: Model>(&'a self, data: &'a [T]) -> UpsertClause<'a, T, Self> {
+        Executor::upsert_many(self, data)
+    }
+
+    pub fn update<'a, T: Model, U: UpdateModel>(
+        &'a self,
+        data: &'a U,
+    ) -> WhereUpdateClause<'a, T, U, Self> {
+        Executor::update(self, data)
+    }
+
+    pub fn delete<T: Model>(&self) -> WhereSelectClause<T, Self> {
+        Executor::delete(self)
+    }
+
+    pub async fn find<T: PrimaryKey>(&self, id: &T::Key) -> Result<Option<T>> {
+        Executor::find(self, id).await
+    }
+
+    pub async fn find_many<T: PrimaryKey>(&self, ids: &[T::Key]) -> Result<Vec<T>> {
+        Executor::find_many(self, ids).await
+    }
+
+    pub async fn update_by_id<T: PrimaryKey, U: UpdateModel + Sync>(
+        &self,
+        id: &T::Key,
+        data: &U,
+    ) -> Result<Option<T>> {
+        Executor::update_by_id(self, id, data).await
+    }
+
+    pub async fn delete_by_id<T: PrimaryKey>(&self, id: &T::Key) -> Result<Option<T>> {
+        Executor::delete_by_id(self, id).await
+    }
+
+    pub async fn save<T: PrimaryKey + Sync>(&self, data: &T) -> Result<T> {
+        Executor::save(self, data).await
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(async move {
+                        if let Err(e) = conn.batch_execute("ROLLBACK").await {
+                            debug!("failed to rollback dropped transaction: {}", e);
+                        }
+                    });
+                }
+                // without a runtime to await it, still queue the rollback ahead of whatever
+                // the next borrower of the connection sends
+                Err(_) => send_batch(&conn, "ROLLBACK"),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Executor for Transaction {
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>> {
+        Executor::query(self.client(), query, params).await
+    }
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>> {
+        Executor::query_opt(self.client(), query, params).await
+    }
+
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row> {
+        Executor::query_one(self.client(), query, params).await
+    }
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64> {
+        Executor::execute(self.client(), query, params).await
+    }
+
+    async fn checkout(&self) -> Result<ClientGuard<'_>> {
+        Ok(Box::new(self.client()))
+    }
+
+    fn in_transaction(&self) -> bool {
+        true
+    }
+}
+
+/// Statements on one connection that take effect together, rolled back if dropped.
+pub struct Atomic<'a> {
+    client: ClientGuard<'a>,
+    nested: bool,
+    done: bool,
+}
+
+impl<'a> Atomic<'a> {
+    pub(crate) async fn begin<E: Executor>(executor: &'a E) -> Result<Atomic<'a>> {
+        let nested = executor.in_transaction();
+        let atomic = Atomic {
+            client: executor.checkout().await?,
+            nested,
+            done: false,
+        };
+        let query = if nested {
+            "SAVEPOINT dojo_atomic"
+        } else {
+            "BEGIN"
+        };
+        debug!("query: {}", query);
+        atomic.client.batch_execute(query).await?;
+
+        Ok(atomic)
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn rollback_query(&self) -> &'static str {
+        if self.nested {
+            "ROLLBACK TO SAVEPOINT dojo_atomic; RELEASE SAVEPOINT dojo_atomic"
+        } else {
+            "ROLLBACK"
+        }
+    }
+
+    pub(crate) async fn commit(mut self) -> Result<()> {
+        let query = if self.nested {
+            "RELEASE SAVEPOINT dojo_atomic"
+        } else {
+            "COMMIT"
+        };
+        debug!("query: {}", query);
+        self.client.batch_execute(query).await?;
+        self.done = true;
+
+        Ok(())
+    }
+
+    pub(crate) async fn rollback(mut self) -> Result<()> {
+        self.done = true;
+        let query = self.rollback_query();
+        debug!("query: {}", query);
+        Ok(self.client.batch_execute(query).await?)
+    }
+}
+
+impl Drop for Atomic<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            send_batch(&self.client, self.rollback_query());
+        }
+    }
+}
+
+#[async_trait]
+impl Executor for Atomic<'_> {
+    async fn query(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>> {
+        Executor::query(self.client(), query, params).await
+    }
+
+    async fn query_opt(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>> {
+        Executor::query_opt(self.client(), query, params).await
+    }
+
+    async fn query_one(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Row> {
+        Executor::query_one(self.client(), query, params).await
+    }
+
+    async fn execute(&self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64> {
+        Executor::execute(self.client(), query, params).await
+    }
+
+    async fn checkout(&self) -> Result<ClientGuard<'_>> {
+        Ok(Box::new(self.client()))
+    }
+
+    fn in_transaction(&self) -> bool {
+        true
+    }
+}
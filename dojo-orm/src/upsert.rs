@@ -0,0 +1,167 @@
+use tokio_postgres::Row;
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{check_column, columns, quote_ident};
+use crate::insert::{insert_sql, MAX_PARAMS};
+use crate::model::{Model, UpdateModel};
+use crate::transaction::Atomic;
+use crate::types::ToSql;
+
+/// What an `ON CONFLICT` clause matches on.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ConflictTarget<'a> {
+    Columns(&'a [&'a str]),
+    Constraint(&'a str),
+}
+
+/// `INSERT ... ON CONFLICT`, built with `upsert` / `upsert_many`.
+pub struct UpsertClause<'a, T, E>
+where
+    T: Model,
+    E: Executor,
+{
+    pub(crate) executor: &'a E,
+    pub(crate) data: &'a [T],
+    pub(crate) target: Option<ConflictTarget<'a>>,
+    pub(crate) excluded: Vec<&'a str>,
+    pub(crate) columns: Vec<&'a str>,
+    pub(crate) params: Vec<&'a (dyn ToSql + Sync)>,
+}
+
+impl<'a, T, E> UpsertClause<'a, T, E>
+where
+    T: Model,
+    E: Executor,
+{
+    /// Conflicts on the unique index over exactly these columns.
+    pub fn on_conflict(&'a mut self, columns: &'a [&'a str]) -> &'a mut Self {
+        self.target = Some(ConflictTarget::Columns(columns));
+        self
+    }
+
+    /// Conflicts on a named unique or exclusion constraint.
+    pub fn on_constraint(&'a mut self, constraint: &'a str) -> &'a mut Self {
+        self.target = Some(ConflictTarget::Constraint(constraint));
+        self
+    }
+
+    /// Overwrites `columns` of the existing row with `EXCLUDED`.
+    pub fn do_update(&'a mut self, columns: &[&'a str]) -> &'a mut Self {
+        self.excluded.extend_from_slice(columns);
+        self
+    }
+
+    /// Sets the existing row's columns from `data`, as `update` would.
+    pub fn do_update_with<U: UpdateModel>(&'a mut self, data: &'a U) -> &'a mut Self {
+        self.columns.extend(data.columns());
+        self.params.extend(data.params());
+        self
+    }
+
+    /// Leaves conflicting rows as they are. This is the default.
+    pub fn do_nothing(&'a mut self) -> &'a mut Self {
+        self.excluded.clear();
+        self.columns.clear();
+        self.params.clear();
+        self
+    }
+
+    /// The statement upserting all of `data` at once.
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        self.build_chunk(self.data)
+    }
+
+    fn build_chunk(&'a self, data: &'a [T]) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
+        let mut params_index = 1;
+
+        let mut params = vec![];
+        let mut query = insert_sql(data, &mut params_index, &mut params);
+
+        query.push_str(" ON CONFLICT");
+        match self.target {
+            Some(ConflictTarget::Columns(columns)) => {
+                let mut quoted = vec![];
+                for column in columns {
+                    check_column::<T>(column)?;
+                    quoted.push(quote_ident(column));
+                }
+                query.push_str(&format!(" ({})", quoted.join(", ")));
+            }
+            Some(ConflictTarget::Constraint(constraint)) => {
+                query.push_str(" ON CONSTRAINT ");
+                query.push_str(&quote_ident(constraint));
+            }
+            None => {}
+        }
+
+        let mut sets = vec![];
+        for column in &self.excluded {
+            check_column::<T>(column)?;
+            let column = quote_ident(column);
+            sets.push(format!("{} = EXCLUDED.{}", column, column));
+        }
+        for column in &self.columns {
+            check_column::<T>(column)?;
+            sets.push(format!("{} = ${}", quote_ident(column), params_index));
+            params_index += 1;
+        }
+        params.extend_from_slice(&self.params);
+
+        if sets.is_empty() {
+            query.push_str(" DO NOTHING");
+        } else if self.target.is_none() {
+            // Postgres only infers the conflicting row for DO NOTHING
+            return Err(Error::InvalidQuery(
+                "DO UPDATE needs on_conflict or on_constraint".to_string(),
+            ));
+        } else {
+            query.push_str(" DO UPDATE SET ");
+            query.push_str(&sets.join(", "));
+        }
+
+        query.push_str(" RETURNING ");
+        query.push_str(&columns::<T>());
+
+        Ok((query, params))
+    }
+
+    /// The inserted or updated row, or `None` when it conflicted under `DO NOTHING`.
+    pub async fn first(&'a self) -> Result<Option<T>> {
+        Ok(self.all().await?.into_iter().next())
+    }
+
+    /// The inserted and updated rows.
+    pub async fn all(&'a self) -> Result<Vec<T>> {
+        if self.data.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // every statement binds the `do_update_with` values once, besides its rows
+        let chunk_size =
+            (MAX_PARAMS.saturating_sub(self.params.len()) / T::COLUMNS.len().max(1)).max(1);
+        if self.data.len() <= chunk_size {
+            let rows = self.upsert_chunk(self.executor, self.data).await?;
+            return rows.into_iter().map(T::from_row).collect();
+        }
+
+        let atomic = Atomic::begin(self.executor).await?;
+        let mut upserted = vec![];
+        for chunk in self.data.chunks(chunk_size) {
+            upserted.extend(self.upsert_chunk(atomic.client(), chunk).await?);
+        }
+        atomic.commit().await?;
+
+        upserted.into_iter().map(T::from_row).collect()
+    }
+
+    async fn upsert_chunk<X>(&'a self, executor: &X, chunk: &'a [T]) -> Result<Vec<Row>>
+    where
+        X: Executor + ?Sized,
+    {
+        let (query, params) = self.build_chunk(chunk)?;
+        debug!("query: {}, params: {:?}", query, params);
+        executor.query(query.as_str(), &params).await
+    }
+}
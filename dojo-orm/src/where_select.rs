@@ -1,37 +1,58 @@
-use std::collections::HashMap;
+use std::future::Future;
 use std::marker::PhantomData;
 
+use futures_util::Stream;
 use tracing::debug;
 
+use crate::batch::BatchClause;
 use crate::cursor::order_by::CursorOrderByClause;
-use crate::model::Model;
-use crate::ops::{Op, OpValue, OpValueType};
-use crate::order_by::{Order, OrderByClause};
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::ident::{columns, table};
+use crate::model::{Model, PrimaryKey, Projection};
+use crate::ops::{where_sql, Op};
+use crate::order_by::{OrderBy, OrderByClause};
 use crate::pagination::Cursor;
-use crate::pool::*;
+use crate::select::SelectClause;
+use crate::stream::query_stream;
 use crate::types::ToSql;
 
-pub struct WhereSelectClause<'a, T>
+pub struct WhereSelectClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub(crate) pool: &'a Pool<PostgresConnectionManager<NoTls>>,
+    pub(crate) executor: &'a E,
     pub(crate) params: Vec<&'a (dyn ToSql + Sync)>,
     pub(crate) ops: Vec<Op<'a>>,
     pub(crate) is_delete: bool,
     pub(crate) _t: PhantomData<T>,
 }
 
-impl<'a, T> WhereSelectClause<'a, T>
+impl<'a, T, E> WhereSelectClause<'a, T, E>
 where
     T: Model,
+    E: Executor,
 {
-    pub fn order_by(&'a self, order: (&'a str, Order)) -> OrderByClause<'a, T> {
+    pub fn order_by(&'a self, order: OrderBy<'a>) -> OrderByClause<'a, T, E> {
         OrderByClause {
-            pool: &self.pool,
+            executor: self.executor,
             params: &self.params,
             ops: &self.ops,
-            orders: HashMap::from([order]),
+            orders: vec![order],
+            _t: PhantomData,
+        }
+    }
+
+    /// Reads only the columns and expressions of `P` rather than whole rows of `T`.
+    pub fn select<P: Projection>(&'a self) -> SelectClause<'a, T, P, E> {
+        SelectClause {
+            executor: self.executor,
+            params: &self.params,
+            ops: &self.ops,
+            orders: vec![],
+            limit: None,
+            offset: None,
             _t: PhantomData,
         }
     }
@@ -41,98 +62,116 @@ where
         self
     }
 
+    pub fn where_opt(&'a mut self, op: Option<Op<'a>>) -> &'a mut Self {
+        self.ops.extend(op);
+        self
+    }
+
+    /// Keyset pagination between two cursors, both exclusive.
     pub fn cursor(
         &'a mut self,
         before: &'a Option<Cursor>,
         after: &'a Option<Cursor>,
-    ) -> CursorOrderByClause<'a, T> {
-        if let Some(value) = before {
-            self.ops.push(Op::Value(OpValue {
-                ty: OpValueType::Value,
-                column: value.field.clone().into(),
-                op: ">=",
-                value,
-            }));
-        }
-
-        if let Some(value) = after {
-            self.ops.push(Op::Value(OpValue {
-                ty: OpValueType::Value,
-                column: value.field.clone().into(),
-                op: "<=",
-                value,
-            }));
-        }
+    ) -> CursorOrderByClause<'a, T, E> {
+        self.connection(after.clone(), before.clone(), None, None)
+    }
 
+    /// Keyset pagination driven by the Relay connection arguments.
+    pub fn connection(
+        &'a self,
+        after: Option<Cursor>,
+        before: Option<Cursor>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> CursorOrderByClause<'a, T, E> {
         CursorOrderByClause {
-            pool: &self.pool,
+            executor: self.executor,
             params: &self.params,
             ops: &self.ops,
             orders: vec![],
             before,
             after,
+            first,
+            last,
             _t: PhantomData,
         }
     }
 
-    pub fn build(&'a self) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    pub fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
         let mut params_index = 1;
 
         let mut params = self.params.clone();
         let mut query = if self.is_delete {
-            format!("DELETE FROM {}", T::NAME)
+            format!("DELETE FROM {}", table::<T>())
         } else {
             let mut query = "SELECT ".to_string();
-            query.push_str(&T::COLUMNS.join(", "));
+            query.push_str(&columns::<T>());
             query.push_str(" FROM ");
-            query.push_str(T::NAME);
+            query.push_str(&table::<T>());
 
             query
         };
 
-        let mut ands = vec![];
-        for op in &self.ops {
-            let (q, p) = op.sql(&mut params_index);
-            ands.push(q);
-            params.extend_from_slice(&p);
-        }
-        if !ands.is_empty() {
-            let and = ands.join(" AND ");
-            query.push_str(" WHERE ");
-            query.push_str(&and);
-        }
+        query.push_str(&where_sql::<T>(&self.ops, &mut params_index, &mut params)?);
 
         if self.is_delete {
             query.push_str(" RETURNING ");
-            query.push_str(&T::COLUMNS.join(", "));
+            query.push_str(&columns::<T>());
         }
 
-        (query, params)
+        Ok((query, params))
     }
 
-    pub async fn first(&'a mut self) -> anyhow::Result<Option<T>> {
-        let (query, params) = self.build();
+    pub async fn first(&'a mut self) -> Result<Option<T>> {
+        let (query, params) = self.build()?;
         let query = if self.is_delete {
             query
         } else {
             format!("{} LIMIT 1", query)
         };
         debug!("query: {}, params: {:?}", query, params);
-        let conn = self.pool.get().await?;
-
-        conn.query_opt(query.as_str(), &params)
+        self.executor
+            .query_opt(query.as_str(), &params)
             .await?
             .map(T::from_row)
             .transpose()
     }
 
-    pub async fn all(&'a mut self) -> anyhow::Result<Vec<T>> {
-        let (query, params) = self.build();
-        debug!("query: {}, params: {:?}", query, params);
-        let conn = self.pool.get().await?;
+    /// Walks the matching rows in primary key order, `size` at a time. See [`BatchClause`].
+    pub fn batches(&'a self, size: i64) -> BatchClause<'a, T, E>
+    where
+        T: PrimaryKey,
+    {
+        BatchClause {
+            executor: self.executor,
+            params: &self.params,
+            ops: &self.ops,
+            size: size.max(1),
+            after: None,
+        }
+    }
+
+    /// Calls `f` with the matching rows in primary key order, `size` at a time.
+    pub async fn each_batch<F, Fut, Err>(&'a self, size: i64, f: F) -> Result<(), Err>
+    where
+        T: PrimaryKey,
+        F: FnMut(Vec<T>) -> Fut,
+        Fut: Future<Output = Result<(), Err>>,
+        Err: From<Error>,
+    {
+        self.batches(size).each(f).await
+    }
 
+    /// Streams the rows instead of collecting them.
+    pub fn stream(&'a self) -> impl Stream<Item = Result<T>> + 'a {
+        query_stream(self.executor, self.build())
+    }
+
+    pub async fn all(&'a mut self) -> Result<Vec<T>> {
+        let (query, params) = self.build()?;
+        debug!("query: {}, params: {:?}", query, params);
         let mut rows = vec![];
-        for row in conn.query(query.as_str(), &params).await? {
+        for row in self.executor.query(query.as_str(), &params).await? {
             rows.push(T::from_row(row)?);
         }
 
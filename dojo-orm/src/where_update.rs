@@ -1,20 +1,21 @@
 use std::marker::PhantomData;
-use std::ops::DerefMut;
 use tracing::debug;
 
-use crate::pool::*;
 use crate::types::ToSql;
 
-use crate::limit::LimitClause;
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::ident::{check_column, columns, quote_ident, table};
 use crate::model::{Model, UpdateModel};
-use crate::ops::Op;
+use crate::ops::{where_sql, Op};
 
-pub struct WhereUpdateClause<'a, T, U>
+pub struct WhereUpdateClause<'a, T, U, E>
 where
     T: Model,
     U: UpdateModel,
+    E: Executor,
 {
-    pub(crate) pool: &'a Pool<PostgresConnectionManager<NoTls>>,
+    pub(crate) executor: &'a E,
     pub(crate) params: Vec<&'a (dyn ToSql + Sync)>,
     pub(crate) columns: Vec<&'a str>,
     pub(crate) ops: Vec<Op<'a>>,
@@ -22,66 +23,50 @@ where
     pub(crate) _u: PhantomData<U>,
 }
 
-impl<'a, T, U> WhereUpdateClause<'a, T, U>
+impl<'a, T, U, E> WhereUpdateClause<'a, T, U, E>
 where
     T: Model,
     U: UpdateModel,
+    E: Executor,
 {
-    fn limit(&'a self, limit: i32) -> LimitClause<'a, T> {
-        LimitClause {
-            params: &self.params,
-            ops: &self.ops,
-            orders: None,
-            limit,
-            _t: PhantomData,
-        }
-    }
-
     pub fn where_by(&'a mut self, op: Op<'a>) -> &'a mut Self {
         self.ops.push(op);
         self
     }
 
-    fn build(&'a self) -> (String, Vec<&'a (dyn ToSql + Sync)>) {
+    pub fn where_opt(&'a mut self, op: Option<Op<'a>>) -> &'a mut Self {
+        self.ops.extend(op);
+        self
+    }
+
+    fn build(&'a self) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>)> {
         let mut params_index = 1;
 
         let mut params = self.params.clone();
         let mut query = "UPDATE ".to_string();
-        query.push_str(T::NAME);
+        query.push_str(&table::<T>());
         query.push_str(" SET ");
 
         let mut sets = vec![];
         for column in &self.columns {
-            sets.push(format!("{} = ${}", column, params_index));
+            check_column::<T>(column)?;
+            sets.push(format!("{} = ${}", quote_ident(column), params_index));
             params_index += 1;
         }
         query.push_str(&sets.join(", "));
 
-        let mut ands = vec![];
-        for op in &self.ops {
-            let (q, p) = op.sql(&mut params_index);
-            ands.push(q);
-            params.extend_from_slice(&p);
-        }
-        if !query.is_empty() {
-            let and = ands.join(" AND ");
-            query.push_str(" WHERE ");
-            query.push_str(&and);
-        }
+        query.push_str(&where_sql::<T>(&self.ops, &mut params_index, &mut params)?);
 
         query.push_str(" RETURNING ");
-        query.push_str(&T::COLUMNS.join(", "));
+        query.push_str(&columns::<T>());
 
-        (query, params)
+        Ok((query, params))
     }
 
-    pub async fn first(&'a self) -> anyhow::Result<Option<T>> {
-        let (query, params) = self.build();
+    pub async fn first(&'a self) -> Result<Option<T>> {
+        let (query, params) = self.build()?;
         debug!("query: {}, params: {:?}", query, params);
-        let mut conn = self.pool.get().await?;
-        let client = conn.deref_mut();
-
-        client
+        self.executor
             .query_opt(query.as_str(), &params)
             .await?
             .map(T::from_row)
@@ -0,0 +1,53 @@
+use base64ct::{Base64UrlUnpadded, Encoding};
+
+use dojo_orm::pagination::{set_cursor_key, Cursor, CursorKey, OffsetEncodedError};
+
+// one test, since the cursor key is process-wide
+#[test]
+fn test_cursor_key() {
+    let cursor = Cursor::new("created_at", chrono::Utc::now().naive_utc()).and("id", 42);
+
+    // a random key until one is set
+    let encoded = cursor.encode();
+    assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+
+    set_cursor_key(CursorKey::new("first secret, 32 bytes or longer!"));
+    let encoded = cursor.encode();
+    assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+
+    // flipping a bit anywhere breaks the signature
+    let mut bytes = Base64UrlUnpadded::decode_vec(&encoded).unwrap();
+    let json = String::from_utf8_lossy(&bytes[1..bytes.len() - 32]).to_string();
+    assert!(json.contains("\"id\""));
+    bytes[5] ^= 1;
+    let tampered = Base64UrlUnpadded::encode_string(&bytes);
+    assert!(matches!(
+        Cursor::decode(&tampered),
+        Err(OffsetEncodedError::InvalidSignature)
+    ));
+
+    // switching to encryption keeps accepting signed cursors, and hides the values
+    set_cursor_key(CursorKey::new("first secret, 32 bytes or longer!").encrypted());
+    assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+    let encrypted = cursor.encode();
+    let bytes = Base64UrlUnpadded::decode_vec(&encrypted).unwrap();
+    assert!(!String::from_utf8_lossy(&bytes).contains("\"id\""));
+    assert_eq!(Cursor::decode(&encrypted).unwrap(), cursor);
+
+    // cursors from another key are rejected
+    set_cursor_key(CursorKey::new("second secret, 32 bytes or longer"));
+    assert!(matches!(
+        Cursor::decode(&encoded),
+        Err(OffsetEncodedError::InvalidSignature)
+    ));
+    assert!(matches!(
+        Cursor::decode(&encrypted),
+        Err(OffsetEncodedError::InvalidSignature)
+    ));
+
+    let unknown = Base64UrlUnpadded::encode_string(&[9, 1, 2, 3]);
+    assert!(matches!(
+        Cursor::decode(&unknown),
+        Err(OffsetEncodedError::UnsupportedVersion(9))
+    ));
+}
@@ -26,7 +26,7 @@ async fn test_in_list() {
 
     impl CursorExt<Cursor> for User {
         fn cursor(&self) -> Cursor {
-            Cursor::new("created_at".to_string(), self.created_at.timestamp_micros())
+            Cursor::new("created_at", self.created_at)
         }
     }
 